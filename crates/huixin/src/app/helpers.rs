@@ -6,8 +6,14 @@ pub enum WidgetId {
     MainAppButtonStatusBar,
     MainAppLeftExplorerPanel,
     MainAppExplorerPatternsOrderingDnd,
+    MainAppTopBarComboBoxTicksPerBeat,
+    MainAppTopBarComboBoxSnapDivision,
+    MainAppUnsavedChangesModal,
+    MainAppRecoveryModal,
 
     ErrorModal,
+    NoticeModal,
+    MidiImportModal,
 
     Tester,
     TesterTopUtilBar,
@@ -17,10 +23,27 @@ pub enum WidgetId {
     PatternEditorMidiNotificationBar,
     PatternEditorMidiDetailPanel,
     PatternEditorMidiDetailPanelGrid,
-    PatternEditorMidiComboBoxCommTarget,
+    PatternEditorMidiComboBoxNoteMode,
+    PatternEditorComboBoxCommTarget,
+    PatternEditorMappingEditor,
+    PatternEditorCurveNotificationBar,
+    PatternEditorCurveDetailPanel,
+    PatternEditorCurveComboBoxInterp,
+    PatternEditorCurveSelectedPoint,
+    PatternEditorEventNotificationBar,
+    PatternEditorEventDetailPanel,
+    PatternEditorEventPayloadGrid,
+    PatternEditorEventSelectedEvent,
 
     ConnectionManager,
     ConnectionManagerTargetsOrderingDnd,
+    ConnectionManagerHealthPanel,
+    ConnectionManagerHealthGrid,
+
+    TrafficInspector,
+
+    LoopbackMonitor,
+    LoopbackMonitorEvents,
 
     TrackEditorTopPanel,
     TrackEditorHeaderOrderingDnd,
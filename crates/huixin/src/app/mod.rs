@@ -1,8 +1,8 @@
 use std::{
     fs,
     ops::DerefMut,
-    sync::{Arc, mpsc},
-    thread,
+    path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 
@@ -14,20 +14,24 @@ use lyn_util::egui::text_color;
 use self::{
     helpers::WidgetId,
     tools::{
-        ToolWindow, connection_manager::ConnectionManager, pattern_editor::PatternEditor,
-        tester::Tester,
+        ToolWindow, connection_manager::ConnectionManager, loopback_monitor::LoopbackMonitor,
+        pattern_editor::PatternEditor, tester::Tester, traffic_inspector::TrafficInspector,
+    },
+    widgets::{
+        error_modal::ErrorModal, midi_import::MidiImportDialog, notice_modal::NoticeModal,
+        performance::Performance,
     },
-    widgets::{error_modal::ErrorModal, performance::Performance},
 };
 use crate::{
     APP_ID,
     app::{tools::ToolWindowId, widgets::track_editor::TrackEditor},
     model::{
         pattern::{SheetPatternTrait, SheetPatternType},
-        persistence::{AppStorage, WorkingDirectory},
-        state::{CentralState, UiState},
+        persistence::{AppStorage, WorkingDirectory, snapshot_is_newer, write_file_atomic},
+        smf::{export_smf, import_smf},
+        state::{CentralState, Sheet, UiState},
     },
-    routines::{RoutineId, guardian, instructor, metronome, sheet_reader},
+    routines,
 };
 
 mod helpers;
@@ -43,10 +47,33 @@ pub enum PlayerContext {
     Pattern,
 }
 
+/// An action discarding the current sheet, held back while the user decides about unsaved edits.
+#[derive(Debug, Clone)]
+enum DiscardingAction {
+    New,
+    /// Opens the given project, or asks for one if `None`.
+    Open(Option<PathBuf>),
+    Close,
+}
+
 #[derive(Debug)]
 pub struct MainApp {
     // app states
     working_directory: Option<WorkingDirectory>,
+    /// The project file the sheet is saved to, `None` until it is saved somewhere.
+    project_path: Option<PathBuf>,
+    recent_projects: Vec<PathBuf>,
+    /// Revision of the sheet when it was last saved or opened.
+    saved_revision: u64,
+    pending_action: Option<DiscardingAction>,
+    close_confirmed: bool,
+    window_title: String,
+    /// Revision of the sheet when it was last autosaved.
+    autosaved_revision: u64,
+    last_autosave_time: f64,
+    /// Autosave snapshot found at launch that is newer than the project file.
+    recovery_snapshot: Option<PathBuf>,
+    midi_import: Option<MidiImportDialog>,
 
     // widget states
     performance: Performance,
@@ -59,97 +86,311 @@ pub struct MainApp {
 
 impl MainApp {
     pub fn init() -> Self {
-        let (msg_tx, msg_rx) = mpsc::channel();
         let state = Arc::new(CentralState::init());
 
         let tools: Vec<Box<dyn ToolWindow>> = vec![
             Box::new(Tester::new(state.clone())),
             Box::new(PatternEditor::new(state.clone())),
             Box::new(ConnectionManager::new(state.clone())),
+            Box::new(TrafficInspector::new(state.clone())),
+            Box::new(LoopbackMonitor::new(state.clone())),
         ];
 
-        let routines = vec![
-            (
-                RoutineId::Metronome,
-                thread::spawn({
-                    let state = state.clone();
-                    move || metronome::main(state)
-                }),
-            ),
-            (
-                RoutineId::SheetReader,
-                thread::spawn({
-                    let state = state.clone();
-                    move || sheet_reader::main(state, msg_tx)
-                }),
-            ),
-            (
-                RoutineId::Instructor,
-                thread::spawn({
-                    let state = state.clone();
-                    move || instructor::main(state, msg_rx)
-                }),
-            ),
-        ];
-        thread::spawn({
-            let state = state.clone();
-            move || guardian::main(state, routines)
-        });
+        routines::launch(state.clone());
 
         Self {
             working_directory: None,
+            project_path: None,
+            recent_projects: Vec::new(),
+            saved_revision: state.history_revision(),
+            pending_action: None,
+            close_confirmed: false,
+            window_title: String::new(),
+            autosaved_revision: state.history_revision(),
+            last_autosave_time: 0.,
+            recovery_snapshot: None,
+            midi_import: None,
             performance: Default::default(),
             track_editor: TrackEditor::new(state.clone()),
             tools,
             state,
         }
     }
-    fn persist_sheet(&self) {
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        self.state.history_revision() != self.saved_revision
+    }
+    fn default_project_dir(&self) -> Option<PathBuf> {
+        self.project_path
+            .as_ref()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .or_else(|| self.working_directory.as_ref().map(|cwd| cwd.state_dir()))
+    }
+    fn remember_recent_project(&mut self, path: PathBuf) {
+        self.recent_projects.retain(|p| *p != path);
+        self.recent_projects.insert(0, path);
+        self.recent_projects.truncate(Self::RECENT_PROJECTS_LIMIT);
+    }
+
+    /// Runs the action right away, or asks the user first if there are unsaved edits.
+    fn request_discarding(&mut self, action: DiscardingAction, ctx: &egui::Context) {
+        if self.is_dirty() {
+            self.pending_action = Some(action);
+        } else {
+            self.run_discarding(action, ctx);
+        }
+    }
+    fn run_discarding(&mut self, action: DiscardingAction, ctx: &egui::Context) {
+        // the user chose to let go of the edits, so their snapshot goes as well,
+        // opening only does so once a file is actually picked
+        if !matches!(action, DiscardingAction::Open(_)) {
+            self.discard_autosave();
+        }
+        match action {
+            DiscardingAction::New => {
+                self.state.sheet_reset();
+                self.project_path = None;
+                self.saved_revision = self.state.history_revision();
+            }
+            DiscardingAction::Open(path) => {
+                let path = path.or_else(|| {
+                    let mut dialog = rfd::FileDialog::new()
+                        .add_filter("绘心工程", &[WorkingDirectory::PROJECT_FILE_EXTENSION]);
+                    if let Some(dir) = self.default_project_dir() {
+                        dialog = dialog.set_directory(dir);
+                    }
+                    dialog.pick_file()
+                });
+                if let Some(path) = path {
+                    self.discard_autosave();
+                    self.open_project(path);
+                }
+            }
+            DiscardingAction::Close => {
+                self.close_confirmed = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+    }
+
+    fn open_project(&mut self, path: PathBuf) {
+        let result = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|str| {
+                self.state
+                    .sheet_from_json_str(&str)
+                    .map_err(|e| e.to_string())
+            });
+        match result {
+            Ok(()) => {
+                self.saved_revision = self.state.history_revision();
+                self.project_path = Some(path.clone());
+                self.remember_recent_project(path);
+            }
+            Err(e) => {
+                warn!("Failed to open project file {:?}: {}", path, e);
+                self.state
+                    .app_set_notice_msg(Some(format!("无法读取文件 {}：{e}", path.display())));
+            }
+        }
+    }
+    /// Opens the last project of the working directory, or the one from before project files
+    /// could be chosen.
+    fn open_initial_project(&mut self, last_project: Option<PathBuf>) {
+        let path = last_project.filter(|path| path.exists()).or_else(|| {
+            self.working_directory
+                .as_ref()
+                .map(|cwd| cwd.state_path(APP_ID))
+                .filter(|path| path.exists())
+        });
+        if let Some(path) = path {
+            self.open_project(path);
+        }
+
         let Some(cwd) = self.working_directory.as_ref() else {
             return;
         };
+        let snapshot = cwd.autosave_path(self.project_path.as_deref());
+        if snapshot_is_newer(&snapshot, self.project_path.as_deref()) {
+            self.recovery_snapshot = Some(snapshot);
+        }
+    }
 
-        self.state.worker_spawn_task({
-            let file_content = self.state.sheet_to_json_string_pretty().unwrap();
-            let file = cwd.state_path(APP_ID);
-            move || {
-                if let Err(e) = fs::create_dir_all(file.parent().unwrap()) {
-                    warn!("Failed to create directories for state persistence: {}", e);
-                    return;
-                }
-                if let Err(e) = fs::write(&file, file_content) {
-                    warn!("Failed to persist state to file {:?}: {}", file, e);
-                }
+    fn save_project(&mut self) {
+        match self.project_path.clone() {
+            Some(path) => self.write_project(path),
+            None => self.save_project_as(),
+        }
+    }
+    fn save_project_as(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("绘心工程", &[WorkingDirectory::PROJECT_FILE_EXTENSION])
+            .set_file_name(format!(
+                "{APP_ID}.{}",
+                WorkingDirectory::PROJECT_FILE_EXTENSION
+            ));
+        if let Some(dir) = self.default_project_dir() {
+            let _ = fs::create_dir_all(&dir);
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(mut path) = dialog.save_file() else {
+            return;
+        };
+        if path.extension().is_none() {
+            path.set_extension(WorkingDirectory::PROJECT_FILE_EXTENSION);
+        }
+        self.write_project(path);
+    }
+    fn write_project(&mut self, path: PathBuf) {
+        // edits still being typed belong to what gets saved
+        self.state.history_settle_gesture();
+        let revision = self.state.history_revision();
+        let result = self
+            .state
+            .sheet_to_json_string_pretty()
+            .map_err(|e| e.to_string())
+            .and_then(|file_content| {
+                write_file_atomic(&path, file_content).map_err(|e| e.to_string())
+            });
+        match result {
+            Ok(()) => {
+                // the snapshot of the sheet under its former name is outdated as well
+                self.discard_autosave();
+                self.saved_revision = revision;
+                self.project_path = Some(path.clone());
+                self.remember_recent_project(path);
+            }
+            Err(e) => {
+                warn!("Failed to save project to file {:?}: {}", path, e);
+                self.state
+                    .app_set_notice_msg(Some(format!("无法保存到文件 {}：{e}", path.display())));
+            }
+        }
+    }
+
+    /// Snapshots the sheet in the background once it has unsaved edits for a while, or right away
+    /// when a routine crashed.
+    fn autosave(&mut self, now: f64) {
+        let revision = self.state.history_revision();
+        if !self.is_dirty() || revision == self.autosaved_revision {
+            return;
+        }
+        let crashed = self.state.app_get_err_msg().is_some();
+        if !crashed && now - self.last_autosave_time < Self::AUTOSAVE_INTERVAL_SECS {
+            return;
+        }
+        let Some(cwd) = self.working_directory.as_ref() else {
+            return;
+        };
+        let file = cwd.autosave_path(self.project_path.as_deref());
+        let file_content = match self.state.sheet_to_json_string_pretty() {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Failed to serialize sheet for autosave: {}", e);
+                return;
+            }
+        };
+        self.autosaved_revision = revision;
+        self.last_autosave_time = now;
+        self.state.worker_spawn_task(move || {
+            if let Err(e) = write_file_atomic(&file, file_content) {
+                warn!("Failed to autosave sheet to file {:?}: {}", file, e);
             }
         });
     }
-    fn restore_sheet(&self) {
-        // read the file, use `self.state.sheet_from_json_str` to restore the state
+    fn discard_autosave(&mut self) {
         let Some(cwd) = self.working_directory.as_ref() else {
             return;
         };
-        let state_file = cwd.state_path(APP_ID);
-        if state_file.exists() {
-            match fs::read_to_string(&state_file) {
-                Ok(str) => {
-                    if let Err(e) = self.state.sheet_from_json_str(&str) {
-                        warn!(
-                            "Failed to restore persisted state from file {:?}: {}",
-                            state_file, e
-                        );
-                    };
-                }
-                Err(e) => {
-                    warn!(
-                        "Failed to restore persisted state from file {:?}: {}",
-                        state_file, e
-                    );
-                }
+        let file = cwd.autosave_path(self.project_path.as_deref());
+        if file.exists()
+            && let Err(e) = fs::remove_file(&file)
+        {
+            warn!("Failed to remove autosave file {:?}: {}", file, e);
+        }
+        self.autosaved_revision = self.state.history_revision();
+    }
+    fn recover_autosave(&mut self, snapshot: &Path) {
+        let result = fs::read_to_string(snapshot)
+            .map_err(|e| e.to_string())
+            .and_then(|str| {
+                self.state
+                    .sheet_from_json_str(&str)
+                    .map_err(|e| e.to_string())
+            });
+        // the recovered sheet stays dirty until saved, `saved_revision` is left as is
+        if let Err(e) = result {
+            warn!("Failed to recover autosave file {:?}: {}", snapshot, e);
+            self.state.app_set_notice_msg(Some(format!(
+                "无法恢复自动备份 {}：{e}",
+                snapshot.display()
+            )));
+        }
+    }
+
+    fn import_midi(&mut self) {
+        let mut dialog = rfd::FileDialog::new().add_filter("MIDI 文件", &["mid", "midi"]);
+        if let Some(cwd) = self.working_directory.as_ref() {
+            dialog = dialog.set_directory(&cwd.0);
+        }
+        let Some(path) = dialog.pick_file() else {
+            return;
+        };
+        let ticks_per_beat = self.state.sheet_ticks_per_beat();
+        let result = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| import_smf(&bytes, ticks_per_beat).map_err(|e| e.to_string()));
+        match result {
+            Ok(import) => {
+                let file_name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                self.midi_import = Some(MidiImportDialog::new(file_name, ticks_per_beat, import));
+            }
+            Err(e) => {
+                warn!("Failed to import MIDI file {:?}: {}", path, e);
+                self.state
+                    .app_set_notice_msg(Some(format!("无法导入文件 {}：{e}", path.display())));
             }
         }
     }
 
+    fn export_midi(&mut self) {
+        let file_name = self
+            .project_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or_else(
+                || String::from(APP_ID),
+                |stem| stem.to_string_lossy().into_owned(),
+            );
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("MIDI 文件", &["mid"])
+            .set_file_name(format!("{file_name}.mid"));
+        if let Some(cwd) = self.working_directory.as_ref() {
+            dialog = dialog.set_directory(&cwd.0);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        let result = export_smf(&self.state)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| write_file_atomic(&path, bytes).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to export MIDI file {:?}: {}", path, e);
+            self.state
+                .app_set_notice_msg(Some(format!("无法导出到文件 {}：{e}", path.display())));
+        }
+    }
+
+    const AUTOSAVE_INTERVAL_SECS: f64 = 30.;
+    const RECENT_PROJECTS_LIMIT: usize = 8;
+    const STORAGE_KEY_PROJECT: &str = "project-path";
+    const STORAGE_KEY_RECENT_PROJECTS: &str = "recent-projects";
     const STORAGE_KEY_CWD: &str = "working-directory";
+    const STORAGE_KEY_COMM_LOOKAHEAD: &str = "comm-lookahead";
+    const STORAGE_KEY_REMOTE_CONTROL: &str = "remote-control";
     pub fn prepare_launch(&mut self, cc: &eframe::CreationContext<'_>) {
         let Some(storage) = cc.storage else {
             return;
@@ -157,8 +398,12 @@ impl MainApp {
 
         self.working_directory =
             eframe::get_value(storage, &AppStorage::key(Self::STORAGE_KEY_CWD)).unwrap_or_default();
-
-        self.restore_sheet();
+        self.recent_projects =
+            eframe::get_value(storage, &AppStorage::key(Self::STORAGE_KEY_RECENT_PROJECTS))
+                .unwrap_or_default();
+        let last_project = eframe::get_value(storage, &AppStorage::key(Self::STORAGE_KEY_PROJECT))
+            .unwrap_or_default();
+        self.open_initial_project(last_project);
 
         for tool in self.tools.iter_mut() {
             let key = AppStorage::key(tool.tool_id().to_string());
@@ -171,6 +416,18 @@ impl MainApp {
         *self.state.ui.pattern_editor_size_per_beat.write() =
             eframe::get_value(storage, &AppStorage::key(UiState::STORAGE_KEY_PATTERN_SPB))
                 .unwrap_or(UiState::MIN_SIZE_PER_BEAT);
+        *self.state.ui.snap_division.write() = eframe::get_value(
+            storage,
+            &AppStorage::key(UiState::STORAGE_KEY_SNAP_DIVISION),
+        )
+        .unwrap_or(UiState::DEFAULT_SNAP_DIVISION);
+        self.state.comm_set_lookahead(
+            eframe::get_value(storage, &AppStorage::key(Self::STORAGE_KEY_COMM_LOOKAHEAD))
+                .unwrap_or_default(),
+        );
+        *self.state.remote_config_mut() =
+            eframe::get_value(storage, &AppStorage::key(Self::STORAGE_KEY_REMOTE_CONTROL))
+                .unwrap_or_default();
     }
 }
 
@@ -186,6 +443,11 @@ impl eframe::App for MainApp {
             self.state.player_set_context(PlayerContext::Sheet);
         }
 
+        self.handle_history_shortcuts(ctx);
+        if editing_gesture_active(ctx) {
+            self.state.history_begin_gesture();
+        }
+
         if self.working_directory.is_none() {
             self.draw_placeholder_ui(ctx);
         } else {
@@ -193,10 +455,34 @@ impl eframe::App for MainApp {
             self.draw_active_tool_windows(ctx);
         }
 
-        if ctx.input(|i| i.key_pressed(egui::Key::S) && (i.modifiers.ctrl || i.modifiers.command)) {
-            self.persist_sheet();
+        if !ctx.input(|i| i.pointer.any_down()) && !ctx.wants_keyboard_input() {
+            self.state.history_settle_gesture();
         }
 
+        self.handle_project_shortcuts(ctx);
+        if ctx.input(|i| i.viewport().close_requested()) && !self.close_confirmed && self.is_dirty()
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.pending_action = Some(DiscardingAction::Close);
+        }
+        self.draw_unsaved_prompt(ctx);
+        self.draw_recovery_prompt(ctx);
+        if let Some(dialog) = self.midi_import.as_mut()
+            && dialog.draw(ctx, &self.state)
+        {
+            self.midi_import = None;
+        }
+        self.update_window_title(ctx);
+        self.autosave(ctx.input(|i| i.time));
+
+        let dismissed = self
+            .state
+            .app_get_notice_msg()
+            .as_ref()
+            .is_some_and(|msg| NoticeModal::new(msg).draw(ctx));
+        if dismissed {
+            self.state.app_set_notice_msg(None);
+        }
         if let Some(msg) = self.state.app_get_err_msg().as_ref() {
             ErrorModal::new(msg).draw(ctx);
         }
@@ -209,6 +495,16 @@ impl eframe::App for MainApp {
             &AppStorage::key(Self::STORAGE_KEY_CWD),
             &self.working_directory,
         );
+        eframe::set_value(
+            storage,
+            &AppStorage::key(Self::STORAGE_KEY_PROJECT),
+            &self.project_path,
+        );
+        eframe::set_value(
+            storage,
+            &AppStorage::key(Self::STORAGE_KEY_RECENT_PROJECTS),
+            &self.recent_projects,
+        );
         for tool in self.tools.iter() {
             eframe::set_value(
                 storage,
@@ -226,6 +522,21 @@ impl eframe::App for MainApp {
             &AppStorage::key(UiState::STORAGE_KEY_PATTERN_SPB),
             &self.state.ui.pattern_editor_size_per_beat,
         );
+        eframe::set_value(
+            storage,
+            &AppStorage::key(UiState::STORAGE_KEY_SNAP_DIVISION),
+            &self.state.ui.snap_division,
+        );
+        eframe::set_value(
+            storage,
+            &AppStorage::key(Self::STORAGE_KEY_COMM_LOOKAHEAD),
+            &self.state.comm_lookahead(),
+        );
+        eframe::set_value(
+            storage,
+            &AppStorage::key(Self::STORAGE_KEY_REMOTE_CONTROL),
+            &self.state.remote_config(),
+        );
     }
 
     fn auto_save_interval(&self) -> Duration {
@@ -244,6 +555,7 @@ impl MainApp {
                 {
                     // FIXME:
                     self.working_directory = rfd::FileDialog::new().pick_folder().map(|p| p.into());
+                    self.open_initial_project(None);
                 }
             });
         });
@@ -271,6 +583,23 @@ impl MainApp {
         egui::TopBottomPanel::bottom(WidgetId::MainAppButtonStatusBar).show(ctx, |ui| {
             ui.horizontal(|ui| {
                 self.performance.ui(ui);
+                let jitter = self.state.metro_jitter();
+                if jitter.samples > 0 {
+                    ui.separator();
+                    ui.label(format!(
+                        "Tick 抖动：平均 {:.3} ms / 最大 {:.3} ms",
+                        1e3 * jitter.mean().as_secs_f64(),
+                        1e3 * jitter.max.as_secs_f64(),
+                    ));
+                }
+                let lagged = self.state.metro_lagged_ticks();
+                if lagged > 0 {
+                    ui.separator();
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("已落后 {lagged} 个 tick"),
+                    );
+                }
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     ui.label(env!("BUILD_INFO"));
@@ -306,10 +635,198 @@ impl MainApp {
         }
     }
 
+    fn handle_history_shortcuts(&mut self, ctx: &egui::Context) {
+        // text fields handle their own undo
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let redo = egui::KeyboardShortcut::new(
+            egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+            egui::Key::Z,
+        );
+        let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+        if ctx.input_mut(|i| i.consume_shortcut(&redo)) {
+            self.state.history_redo();
+        } else if ctx.input_mut(|i| i.consume_shortcut(&undo)) {
+            self.state.history_undo();
+        }
+    }
+
+    fn handle_project_shortcuts(&mut self, ctx: &egui::Context) {
+        let save_as = egui::KeyboardShortcut::new(
+            egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+            egui::Key::S,
+        );
+        let save = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::S);
+        let open = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::O);
+        let new = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::N);
+        if ctx.input_mut(|i| i.consume_shortcut(&save_as)) {
+            self.save_project_as();
+        } else if ctx.input_mut(|i| i.consume_shortcut(&save)) {
+            self.save_project();
+        } else if ctx.input_mut(|i| i.consume_shortcut(&open)) {
+            self.request_discarding(DiscardingAction::Open(None), ctx);
+        } else if ctx.input_mut(|i| i.consume_shortcut(&new)) {
+            self.request_discarding(DiscardingAction::New, ctx);
+        }
+    }
+
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let name = self
+            .project_path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map_or_else(
+                || String::from("未命名工程"),
+                |name| name.to_string_lossy().into_owned(),
+            );
+        let title = format!(
+            "{}{name} - {APP_ID}",
+            if self.is_dirty() { "● " } else { "" }
+        );
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    fn draw_unsaved_prompt(&mut self, ctx: &egui::Context) {
+        let Some(action) = self.pending_action.clone() else {
+            return;
+        };
+        egui::Modal::new(WidgetId::MainAppUnsavedChangesModal.into()).show(ctx, |ui| {
+            ui.label(egui::RichText::new("未保存的修改").heading().strong());
+            ui.label("当前工程有尚未保存的修改，是否先保存？");
+            ui.horizontal(|ui| {
+                if ui.button("保存").clicked() {
+                    self.save_project();
+                    self.pending_action = None;
+                    // saving may have been cancelled or failed
+                    if !self.is_dirty() {
+                        self.run_discarding(action.clone(), ctx);
+                    }
+                }
+                if ui.button("不保存").clicked() {
+                    self.pending_action = None;
+                    self.run_discarding(action.clone(), ctx);
+                }
+                if ui.button("取消").clicked() {
+                    self.pending_action = None;
+                }
+            });
+        });
+    }
+
+    fn draw_recovery_prompt(&mut self, ctx: &egui::Context) {
+        let Some(snapshot) = self.recovery_snapshot.clone() else {
+            return;
+        };
+        egui::Modal::new(WidgetId::MainAppRecoveryModal.into()).show(ctx, |ui| {
+            ui.label(egui::RichText::new("发现自动备份").heading().strong());
+            ui.label("上次退出前有未保存的修改，自动备份比已保存的文件更新，是否恢复？");
+            ui.label(egui::RichText::new(snapshot.display().to_string()).weak());
+            ui.horizontal(|ui| {
+                if ui.button("恢复").clicked() {
+                    self.recovery_snapshot = None;
+                    self.recover_autosave(&snapshot);
+                }
+                if ui.button("丢弃").clicked() {
+                    self.recovery_snapshot = None;
+                    self.discard_autosave();
+                }
+            });
+        });
+    }
+
     fn app_menu(&mut self, ui: &mut egui::Ui) {
         MenuButton::from_button(egui::Button::new("󰍜 ").frame_when_inactive(false)).ui(ui, |ui| {
-            if ui.button("保存").clicked() {
-                self.persist_sheet();
+            if ui
+                .add(egui::Button::new("新建").shortcut_text("Ctrl+N"))
+                .clicked()
+            {
+                self.request_discarding(DiscardingAction::New, ui.ctx());
+                ui.close();
+            }
+            if ui
+                .add(egui::Button::new("打开…").shortcut_text("Ctrl+O"))
+                .clicked()
+            {
+                self.request_discarding(DiscardingAction::Open(None), ui.ctx());
+                ui.close();
+            }
+            ui.add_enabled_ui(!self.recent_projects.is_empty(), |ui| {
+                ui.menu_button("最近的工程", |ui| {
+                    let mut chosen = None;
+                    for path in self.recent_projects.iter() {
+                        let name = path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        if ui
+                            .button(name)
+                            .on_hover_text(path.display().to_string())
+                            .clicked()
+                        {
+                            chosen = Some(path.clone());
+                        }
+                    }
+                    if let Some(path) = chosen {
+                        if !path.exists() {
+                            self.recent_projects.retain(|p| *p != path);
+                            self.state.app_set_notice_msg(Some(format!(
+                                "文件 {} 已不存在",
+                                path.display()
+                            )));
+                        } else {
+                            self.request_discarding(DiscardingAction::Open(Some(path)), ui.ctx());
+                        }
+                        ui.close();
+                    }
+                });
+            });
+            ui.separator();
+            if ui
+                .add(egui::Button::new("保存").shortcut_text("Ctrl+S"))
+                .clicked()
+            {
+                self.save_project();
+                ui.close();
+            }
+            if ui
+                .add(egui::Button::new("另存为…").shortcut_text("Ctrl+Shift+S"))
+                .clicked()
+            {
+                self.save_project_as();
+                ui.close();
+            }
+            ui.separator();
+            if ui.button("导入 MIDI…").clicked() {
+                ui.close();
+                self.import_midi();
+            }
+            if ui.button("导出 MIDI…").clicked() {
+                ui.close();
+                self.export_midi();
+            }
+            ui.separator();
+            if ui
+                .add_enabled(
+                    self.state.history_can_undo(),
+                    egui::Button::new("撤销").shortcut_text("Ctrl+Z"),
+                )
+                .clicked()
+            {
+                self.state.history_undo();
+                ui.close();
+            }
+            if ui
+                .add_enabled(
+                    self.state.history_can_redo(),
+                    egui::Button::new("重做").shortcut_text("Ctrl+Shift+Z"),
+                )
+                .clicked()
+            {
+                self.state.history_redo();
                 ui.close();
             }
         });
@@ -384,23 +901,74 @@ impl MainApp {
         };
 
         // bpm control
-        ui.add(
-            egui::DragValue::new(self.state.sheet_bpm_mut().deref_mut())
-                .range(1..=640)
-                .prefix("BPM "),
-        );
+        if let Some(bpm) = self.state.metro_bpm_override() {
+            if ui
+                .button(format!("BPM {bpm}"))
+                .on_hover_text("速度已被远程控制覆盖，点击以恢复工程速度")
+                .clicked()
+            {
+                self.state.metro_override_bpm(None);
+            }
+        } else {
+            ui.add(
+                egui::DragValue::new(self.state.sheet_bpm_mut().deref_mut())
+                    .range(1..=640)
+                    .prefix("BPM "),
+            )
+            .on_hover_text("时间轴轨道上的速度变化会覆盖此值");
+        }
 
         // TODO: impl actual context progress bar
         let limit = self.state.metro_tick_limit();
         ui.add(
             egui::DragValue::new(self.state.sheet_length_in_beats_mut().deref_mut())
-                .range(1..=u64::MAX)
+                .range(1..=Sheet::MAX_BEATS)
                 .prefix("Beats "),
         );
-        ui.add(
-            egui::Slider::new(self.state.metro_tick_mut().deref_mut(), 0..=limit)
-                .suffix(format!("/{limit}")),
-        );
+
+        // tick resolution control
+        let ticks_per_beat = self.state.sheet_ticks_per_beat();
+        egui::ComboBox::from_id_salt(WidgetId::MainAppTopBarComboBoxTicksPerBeat)
+            .selected_text(format!("PPQ {ticks_per_beat}"))
+            .width(80.)
+            .show_ui(ui, |ui| {
+                for choice in Sheet::TICKS_PER_BEAT_CHOICES {
+                    if ui
+                        .selectable_label(choice == ticks_per_beat, choice.to_string())
+                        .clicked()
+                    {
+                        self.state.sheet_set_ticks_per_beat(choice);
+                    }
+                }
+            })
+            .response
+            .on_hover_text("每拍的 tick 数，修改后所有内容会按比例换算");
+
+        // snap control
+        let mut snap_division = self.state.ui.snap_division.write();
+        egui::ComboBox::from_id_salt(WidgetId::MainAppTopBarComboBoxSnapDivision)
+            .selected_text(format!(
+                "吸附 {}",
+                UiState::snap_division_label(*snap_division)
+            ))
+            .width(80.)
+            .show_ui(ui, |ui| {
+                for division in UiState::SNAP_DIVISIONS {
+                    ui.selectable_value(
+                        snap_division.deref_mut(),
+                        division,
+                        UiState::snap_division_label(division),
+                    );
+                }
+            });
+        drop(snap_division);
+        let mut tick = self.state.metro_tick();
+        if ui
+            .add(egui::Slider::new(&mut tick, 0..=limit).suffix(format!("/{limit}")))
+            .changed()
+        {
+            self.state.metro_seek(tick);
+        }
     }
 
     fn toolbar(&mut self, ui: &mut egui::Ui) {
@@ -419,12 +987,17 @@ impl MainApp {
     }
 
     fn explorer(&mut self, ui: &mut egui::Ui) {
-        if ui
-            .add_sized([ui.available_width(), 30.], egui::Button::new("添加片段"))
-            .clicked()
-        {
-            self.state.sheet_add_pattern(SheetPatternType::Midi);
-        };
+        MenuButton::from_button(
+            egui::Button::new("添加片段").min_size(emath::vec2(ui.available_width(), 30.)),
+        )
+        .ui(ui, |ui| {
+            for pattern_type in SheetPatternType::variants() {
+                if ui.button(pattern_type.to_string()).clicked() {
+                    self.state.sheet_add_pattern(*pattern_type);
+                    ui.close();
+                }
+            }
+        });
 
         egui::ScrollArea::vertical().show(ui, |ui| {
             let mut to_be_removed = Vec::new();
@@ -489,3 +1062,21 @@ impl MainApp {
         });
     }
 }
+
+// LYN: Helpers
+
+/// Whether the input of this frame may edit the sheet, edits only ever happen in response to
+/// pointer presses, drags, releases or typing.
+fn editing_gesture_active(ctx: &egui::Context) -> bool {
+    ctx.wants_keyboard_input()
+        || ctx.input(|i| {
+            i.pointer.any_down()
+                || i.pointer.any_released()
+                || i.events.iter().any(|e| {
+                    matches!(
+                        e,
+                        egui::Event::Key { .. } | egui::Event::Text(_) | egui::Event::Paste(_)
+                    )
+                })
+        })
+}
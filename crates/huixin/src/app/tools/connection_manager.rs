@@ -1,7 +1,10 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use egui_dnd::dnd;
-use lyn_util::egui::LynId;
+use lyn_util::comm::Format;
 use parking_lot::RwLock;
 
 use crate::{
@@ -9,7 +12,11 @@ use crate::{
         helpers::WidgetId,
         tools::{ToolWindow, ToolWindowId},
     },
-    model::{comm::CommTarget, state::CentralState},
+    model::{
+        comm::{OverflowPolicy, SendQueue},
+        state::{App, CentralState},
+    },
+    routines::remote_control::RemoteStatus,
 };
 
 #[derive(Debug)]
@@ -24,6 +31,108 @@ impl ConnectionManager {
     }
 }
 
+impl ConnectionManager {
+    fn lookahead(&self, ui: &mut egui::Ui) {
+        let mut lookahead_ms = self.state.comm_lookahead().as_millis() as u64;
+        ui.horizontal(|ui| {
+            ui.label("提前发送");
+            let resp = ui
+                .add(
+                    egui::Slider::new(
+                        &mut lookahead_ms,
+                        0..=App::MAX_COMM_LOOKAHEAD.as_millis() as u64,
+                    )
+                    .suffix(" ms"),
+                )
+                .on_hover_text("指令将提前发送并附带执行时间（OSC 时间标签 / JSON timestamp 字段），由接收端按时执行；为 0 时即时发送");
+            if resp.changed() {
+                self.state
+                    .comm_set_lookahead(Duration::from_millis(lookahead_ms));
+            }
+        });
+    }
+
+    fn health(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("连接状态")
+            .id_salt(WidgetId::ConnectionManagerHealthPanel)
+            .show(ui, |ui| {
+                egui::Grid::new(WidgetId::ConnectionManagerHealthGrid)
+                    .num_columns(6)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in ["目标", "状态", "延迟", "在线时长", "已发送", "流量"]
+                        {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+
+                        let now = Instant::now();
+                        for id in self.state.sheet_targets_ordering().iter() {
+                            let Some(target) = self.state.sheet_get_comm_target(id) else {
+                                continue;
+                            };
+                            let health = self.state.comm_health(id);
+                            let connected = self.state.comm_stream_exists(id);
+
+                            ui.label(target.read().name.as_str());
+                            let status = if connected {
+                                egui::RichText::new("已连接")
+                            } else if let Some(retry_at) = health.retry_at
+                                && retry_at > now
+                            {
+                                egui::RichText::new(format!(
+                                    "{:.1} 秒后重连",
+                                    (retry_at - now).as_secs_f32()
+                                ))
+                                .color(ui.visuals().error_fg_color)
+                            } else {
+                                egui::RichText::new("连接中").color(ui.visuals().warn_fg_color)
+                            };
+                            let status = ui.label(status);
+                            if let Some(err) = health.last_error.as_ref() {
+                                status.on_hover_text(format!("最近错误：{err}"));
+                            }
+                            ui.label(health.latency.map_or("-".to_string(), |latency| {
+                                format!("{:.1} ms", latency.as_secs_f64() * 1000.)
+                            }));
+                            ui.label(
+                                health
+                                    .connected_since
+                                    .filter(|_| connected)
+                                    .map_or("-".to_string(), |since| format_uptime(now - since)),
+                            );
+                            ui.label(format!("{} 条", health.msgs_sent));
+                            ui.label(format_bytes(health.bytes_sent));
+                            ui.end_row();
+                        }
+                    });
+            });
+        // keep the status ticking
+        ui.ctx().request_repaint_after(Duration::from_millis(500));
+    }
+
+    fn remote_control(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let mut config = self.state.remote_config_mut();
+            ui.checkbox(&mut config.enabled, "远程控制")
+                .on_hover_text("通过 WebSocket 接收 JSON 命令以控制播放，并推送播放状态");
+            ui.add_enabled(
+                !config.enabled,
+                egui::TextEdit::singleline(&mut config.addr).desired_width(140.),
+            );
+        });
+        match self.state.remote_status() {
+            RemoteStatus::Disabled => {}
+            RemoteStatus::Listening { clients } => {
+                ui.label(format!("正在监听，{clients} 个客户端已连接"));
+            }
+            RemoteStatus::Failed(e) => {
+                ui.colored_label(ui.visuals().error_fg_color, format!("无法监听：{e}"));
+            }
+        }
+    }
+}
+
 impl ToolWindow for ConnectionManager {
     fn tool_id(&self) -> ToolWindowId {
         ToolWindowId::ConnectionManager
@@ -72,6 +181,7 @@ impl ToolWindow for ConnectionManager {
                                         ordering_id_to_be_removed.push(id.clone());
                                         return;
                                     };
+                                    self.state.history_touch_target(id);
                                     let Some(mut guard) = arc.try_write() else {
                                         ui.horizontal(|ui| {
                                             // TODO: better locking indication
@@ -85,24 +195,45 @@ impl ToolWindow for ConnectionManager {
                                         });
 
                                         let target_id = id.clone();
-                                        ui.label(if self.state.comm_stream_exists(&target_id) {
+                                        let stats = self
+                                            .state
+                                            .comm_get_queue(&target_id)
+                                            .map(|queue| queue.stats())
+                                            .unwrap_or_default();
+                                        ui.label(if !self.state.comm_stream_exists(&target_id) {
+                                            egui::RichText::new(" ").color(ecolor::Color32::RED)
+                                        } else if stats.dropped > 0 {
                                             egui::RichText::new(" ")
+                                                .color(ui.visuals().warn_fg_color)
                                         } else {
-                                            egui::RichText::new(" ").color(ecolor::Color32::RED)
-                                        });
+                                            egui::RichText::new(" ")
+                                        })
+                                        .on_hover_text(format!(
+                                            "队列：{} / {}\n已发送：{}\n溢出丢弃：{}\n发送失败：{}",
+                                            stats.depth,
+                                            SendQueue::CAPACITY,
+                                            stats.sent,
+                                            stats.dropped,
+                                            stats.failed,
+                                        ));
                                         ui.add_sized(
                                             [80., ui.available_height()],
                                             egui::TextEdit::singleline(&mut guard.name),
                                         );
-                                        let addr_resp = ui.add_sized(
-                                            [140., ui.available_height()],
-                                            egui::TextEdit::singleline(&mut guard.addr),
-                                        );
+                                        let uses_addr = guard.format != Format::Loopback;
+                                        let addr_resp = ui
+                                            .add_enabled_ui(uses_addr, |ui| {
+                                                ui.add_sized(
+                                                    [140., ui.available_height()],
+                                                    egui::TextEdit::singleline(&mut guard.addr),
+                                                )
+                                            })
+                                            .inner;
                                         let format_changed = egui::ComboBox::new(&target_id, "")
                                             .selected_text(guard.format.to_string())
                                             .show_ui(ui, |ui| {
                                                 let mut changed = false;
-                                                for format in lyn_util::comm::Format::variants() {
+                                                for format in Format::variants() {
                                                     changed |= ui
                                                         .selectable_value(
                                                             &mut guard.format,
@@ -114,6 +245,19 @@ impl ToolWindow for ConnectionManager {
                                                 changed
                                             })
                                             .inner;
+                                        egui::ComboBox::new((&target_id, "overflow"), "")
+                                            .selected_text(guard.overflow.to_string())
+                                            .show_ui(ui, |ui| {
+                                                for policy in OverflowPolicy::variants() {
+                                                    ui.selectable_value(
+                                                        &mut guard.overflow,
+                                                        *policy,
+                                                        policy.to_string(),
+                                                    );
+                                                }
+                                            })
+                                            .response
+                                            .on_hover_text("发送队列已满时的处理方式");
                                         if addr_resp.changed() || format_changed.is_some_and(|v| v)
                                         {
                                             self.state.comm_drop_stream(&target_id);
@@ -135,6 +279,10 @@ impl ToolWindow for ConnectionManager {
                             if ui.button("新增通讯目标").clicked() {
                                 self.state.sheet_add_comm_target();
                             };
+                            self.health(ui);
+                            ui.separator();
+                            self.lookahead(ui);
+                            self.remote_control(ui);
                         });
                 });
                 ui.allocate_space(emath::vec2(350., ui.available_height()));
@@ -142,3 +290,28 @@ impl ToolWindow for ConnectionManager {
         self.open = open;
     }
 }
+
+// LYN: Helpers
+
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    match secs / 3600 {
+        0 => format!("{:02}:{:02}", secs / 60, secs % 60),
+        hours => format!("{hours}:{:02}:{:02}", secs / 60 % 60, secs % 60),
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
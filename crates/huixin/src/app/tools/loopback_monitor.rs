@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use crate::{
+    app::{
+        helpers::WidgetId,
+        tools::{ToolWindow, ToolWindowId},
+    },
+    model::{
+        DEFAULT_SELECTABLE_COLOR,
+        loopback::{LoopbackEvent, LoopbackNote, unix_now},
+        state::CentralState,
+    },
+};
+
+/// Seconds of the past shown, the rest of the width shows what was sent ahead.
+const PAST_SECS: f64 = 5.;
+const FUTURE_SECS: f64 = 1.;
+/// Fewest keys shown, centered on the notes received.
+const MIN_KEY_RANGE: u8 = 24;
+const SHOWN_EVENTS: usize = 8;
+
+#[derive(Debug)]
+pub struct LoopbackMonitor {
+    open: bool,
+    state: Arc<CentralState>,
+}
+
+impl LoopbackMonitor {
+    pub fn new(state: Arc<CentralState>) -> Self {
+        Self { open: false, state }
+    }
+}
+
+impl ToolWindow for LoopbackMonitor {
+    fn tool_id(&self) -> ToolWindowId {
+        ToolWindowId::LoopbackMonitor
+    }
+    fn icon(&self) -> String {
+        "󰙽 ".to_string()
+    }
+
+    fn window_open(&self) -> bool {
+        self.open
+    }
+
+    fn window_open_mut(&mut self) -> &mut bool {
+        &mut self.open
+    }
+
+    fn toggle_open(&mut self, open: Option<bool>) {
+        if let Some(open) = open {
+            self.open = open;
+        } else {
+            self.open = !self.open;
+        }
+    }
+    fn draw(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("回环监视")
+            .id(WidgetId::LoopbackMonitor.into())
+            .collapsible(true)
+            .open(&mut open)
+            .min_size(emath::vec2(360., 200.))
+            .default_size(emath::vec2(560., 320.))
+            .show(ctx, |ui| {
+                self.top_bar(ui);
+                ui.separator();
+                // copied out, so the writers are never held up by drawing
+                let (notes, events) = {
+                    let sink = self.state.loopback_sink();
+                    let events = sink.events().iter().rev().take(SHOWN_EVENTS).cloned();
+                    (
+                        sink.notes().iter().cloned().collect::<Vec<_>>(),
+                        events.collect::<Vec<_>>(),
+                    )
+                };
+                Self::events(ui, &events);
+                Self::note_roll(ui, &notes);
+            });
+        self.open = open;
+        // notes scroll by
+        ctx.request_repaint();
+    }
+}
+
+impl LoopbackMonitor {
+    fn top_bar(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let mut sink = self.state.loopback_sink_mut();
+            let mut beep = sink.beep();
+            if ui
+                .checkbox(&mut beep, "提示音")
+                .on_hover_text("收到音符时发出简单的提示音")
+                .changed()
+            {
+                sink.set_beep(beep);
+            }
+            if ui.button("清空").clicked() {
+                sink.clear();
+            }
+            ui.separator();
+            ui.label(format!("已接收 {} 条指令", sink.received()))
+                .on_hover_text("将通讯目标的格式设为“内置回环”，发往它的指令就会显示在这里");
+        });
+    }
+
+    fn events(ui: &mut egui::Ui, events: &[LoopbackEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new("其他指令")
+            .id_salt(WidgetId::LoopbackMonitorEvents)
+            .show(ui, |ui| {
+                let now = unix_now();
+                for event in events {
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("{:>6.2}s", event.time - now));
+                        ui.label(event.target.as_str());
+                        ui.strong(event.tag.as_str());
+                        ui.monospace(event.data.as_str());
+                    });
+                }
+            });
+    }
+
+    /// Notes scroll from right to left, the line marks now.
+    fn note_roll(ui: &mut egui::Ui, notes: &[LoopbackNote]) {
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0., ui.visuals().extreme_bg_color);
+
+        let now = unix_now();
+        let (from, to) = (now - PAST_SECS, now + FUTURE_SECS);
+        let x = |time: f64| rect.left() + ((time - from) / (to - from)) as f32 * rect.width();
+
+        let (low, high) = key_range(notes);
+        let key_height = rect.height() / (high - low + 1) as f32;
+        for key in low..=high {
+            // black keys get a darker lane
+            if matches!(key % 12, 1 | 3 | 6 | 8 | 10) {
+                let top = rect.bottom() - (key - low + 1) as f32 * key_height;
+                painter.rect_filled(
+                    egui::Rect::from_x_y_ranges(rect.x_range(), top..=top + key_height),
+                    0.,
+                    ui.visuals().faint_bg_color,
+                );
+            }
+        }
+
+        for note in notes {
+            let end = note.end.unwrap_or(now).max(note.start);
+            if end < from || note.start > to || !(low..=high).contains(&note.midicode) {
+                continue;
+            }
+            let top = rect.bottom() - (note.midicode - low + 1) as f32 * key_height;
+            let left = x(note.start);
+            let note_rect = egui::Rect::from_min_max(
+                emath::pos2(left, top),
+                emath::pos2(x(end).max(left + 2.), top + key_height),
+            );
+            let alpha = 0.3 + 0.7 * note.strength;
+            painter.rect_filled(
+                note_rect,
+                1.,
+                DEFAULT_SELECTABLE_COLOR.gamma_multiply(alpha),
+            );
+        }
+
+        painter.vline(
+            x(now),
+            rect.y_range(),
+            egui::Stroke::new(1., ui.visuals().warn_fg_color),
+        );
+        painter.text(
+            rect.left_top() + emath::vec2(4., 2.),
+            egui::Align2::LEFT_TOP,
+            format!("{high}"),
+            egui::FontId::monospace(10.),
+            ui.visuals().weak_text_color(),
+        );
+        painter.text(
+            rect.left_bottom() + emath::vec2(4., -2.),
+            egui::Align2::LEFT_BOTTOM,
+            format!("{low}"),
+            egui::FontId::monospace(10.),
+            ui.visuals().weak_text_color(),
+        );
+    }
+}
+
+// LYN: Helpers
+
+/// Returns the lowest and highest midicode to show, fitting every note.
+fn key_range(notes: &[LoopbackNote]) -> (u8, u8) {
+    let (low, high) = notes
+        .iter()
+        .fold(None, |range: Option<(u8, u8)>, note| {
+            Some(range.map_or((note.midicode, note.midicode), |(low, high)| {
+                (low.min(note.midicode), high.max(note.midicode))
+            }))
+        })
+        .unwrap_or((60, 60));
+    let missing = MIN_KEY_RANGE.saturating_sub(high - low);
+    let low = low.saturating_sub(missing / 2);
+    let high = (low + (high - low).max(MIN_KEY_RANGE)).min(127);
+    (low, high)
+}
@@ -1,8 +1,10 @@
 use std::fmt::{Debug, Display};
 
 pub mod connection_manager;
+pub mod loopback_monitor;
 pub mod pattern_editor;
 pub mod tester;
+pub mod traffic_inspector;
 
 pub trait ToolWindow: Debug {
     fn tool_id(&self) -> ToolWindowId;
@@ -18,6 +20,8 @@ pub enum ToolWindowId {
     PatternEditor,
     ConnectionManager,
     Tester,
+    TrafficInspector,
+    LoopbackMonitor,
 }
 
 impl Display for ToolWindowId {
@@ -26,6 +30,8 @@ impl Display for ToolWindowId {
             ToolWindowId::PatternEditor => "Pattern Editor",
             ToolWindowId::ConnectionManager => "Connection Manager",
             ToolWindowId::Tester => "Tester",
+            ToolWindowId::TrafficInspector => "Traffic Inspector",
+            ToolWindowId::LoopbackMonitor => "Loopback Monitor",
         };
         write!(f, "{}", s)
     }
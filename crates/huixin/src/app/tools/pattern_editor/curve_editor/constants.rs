@@ -0,0 +1,4 @@
+pub const CURVE_CANVAS_HEIGHT: f32 = 320.;
+pub const CURVE_POINT_RADIUS: f32 = 5.;
+pub const CURVE_SAMPLE_STEP: f32 = 2.;
+pub const CURVE_VALUE_GUIDES: u32 = 4;
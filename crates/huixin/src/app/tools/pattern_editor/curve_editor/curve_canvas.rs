@@ -0,0 +1,147 @@
+use crate::model::pattern::{
+    SheetPatternTrait,
+    curve::{CurveInterp, CurvePattern, CurvePoint},
+};
+
+use super::constants::{CURVE_CANVAS_HEIGHT, CURVE_SAMPLE_STEP, CURVE_VALUE_GUIDES};
+
+#[derive(Debug)]
+#[must_use]
+pub struct CurveCanvas<'pat> {
+    size_per_beat: f32,
+    ticks_per_beat: u64,
+    tick_snap: u64,
+    curve_pattern: &'pat mut CurvePattern,
+}
+
+impl<'pat> CurveCanvas<'pat> {
+    pub fn new(
+        size_per_beat: f32,
+        ticks_per_beat: u64,
+        tick_snap: u64,
+        curve_pattern: &'pat mut CurvePattern,
+    ) -> Self {
+        Self {
+            size_per_beat,
+            ticks_per_beat,
+            tick_snap,
+            curve_pattern,
+        }
+    }
+
+    fn calc_tick(&self, x: f32) -> u64 {
+        let ticks = ((x / self.size_per_beat) * self.ticks_per_beat as f32).round() as u64;
+        (ticks + self.tick_snap / 2) / self.tick_snap * self.tick_snap
+    }
+}
+
+impl<'pat> CurveCanvas<'pat> {
+    pub fn show(self, ui: &mut egui::Ui) {
+        let total_width = self.curve_pattern.beats() as f32 * self.size_per_beat;
+        let desired_size = emath::vec2(total_width, CURVE_CANVAS_HEIGHT);
+        let (rect, resp) = ui.allocate_exact_size(desired_size, egui::Sense::click_and_drag());
+
+        let visuals = ui.style().noninteractive();
+        let (min, max) = (self.curve_pattern.min, self.curve_pattern.max);
+
+        if resp.clicked() {
+            let pos = resp.interact_pointer_pos().unwrap();
+            let tick = self
+                .calc_tick(pos.x - rect.left())
+                .min(self.curve_pattern.beats * self.ticks_per_beat);
+            let value = y_to_value(rect, min, max, pos.y).clamp(min, max);
+            self.curve_pattern
+                .add_point(CurvePoint::new(tick, value, CurveInterp::default()));
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter_at(rect);
+
+            // background
+            painter.rect_filled(rect, 0., visuals.bg_fill.linear_multiply(1.5));
+
+            // vertical lines
+            for tick in (0..=self.curve_pattern.beats() * self.ticks_per_beat)
+                .step_by(self.tick_snap as usize)
+            {
+                let x =
+                    rect.left() + (tick as f32 / self.ticks_per_beat as f32) * self.size_per_beat;
+                painter.line_segment(
+                    [emath::pos2(x, rect.top()), emath::pos2(x, rect.bottom())],
+                    egui::Stroke::new(
+                        match (tick % (self.ticks_per_beat * 4), tick % self.ticks_per_beat) {
+                            (0, _) => 0.7,
+                            (_, 0) => 0.4,
+                            _ => 0.2,
+                        },
+                        visuals.fg_stroke.color.linear_multiply(0.5),
+                    ),
+                );
+            }
+
+            // value guides
+            for guide in 0..=CURVE_VALUE_GUIDES {
+                let value = min + (max - min) * guide as f64 / CURVE_VALUE_GUIDES as f64;
+                let y = value_to_y(rect, min, max, value);
+                painter.line_segment(
+                    [emath::pos2(rect.left(), y), emath::pos2(rect.right(), y)],
+                    egui::Stroke::new(0.4, visuals.fg_stroke.color.linear_multiply(0.5)),
+                );
+                painter.text(
+                    emath::pos2(rect.left() + 2., y),
+                    match guide {
+                        0 => egui::Align2::LEFT_BOTTOM,
+                        _ => egui::Align2::LEFT_TOP,
+                    },
+                    format!("{value:.2}"),
+                    egui::FontId::monospace(10.),
+                    visuals.text_color().linear_multiply(0.6),
+                );
+            }
+
+            // curve
+            if self.curve_pattern.points_iter_owned().next().is_some() {
+                let mut points = Vec::new();
+                let mut x = 0.;
+                while x <= rect.width() {
+                    let tick = x / self.size_per_beat * self.ticks_per_beat as f32;
+                    if let Some(value) = self.curve_pattern.value_at(tick as f64) {
+                        points.push(emath::pos2(
+                            rect.left() + x,
+                            value_to_y(rect, min, max, value),
+                        ));
+                    }
+                    x += CURVE_SAMPLE_STEP;
+                }
+                painter.add(egui::Shape::line(
+                    points,
+                    egui::Stroke::new(2., self.curve_pattern.color),
+                ));
+            }
+
+            // border
+            painter.rect_stroke(
+                rect,
+                0.0,
+                (0.4, visuals.fg_stroke.color),
+                egui::StrokeKind::Inside,
+            );
+        }
+    }
+}
+
+// LYN: Helpers
+
+/// Maps a value onto the vertical axis of the canvas, `max` sits at the top.
+#[inline]
+pub fn value_to_y(rect: egui::Rect, min: f64, max: f64, value: f64) -> f32 {
+    let span = (max - min).max(f64::EPSILON);
+    rect.bottom() - ((value - min) / span) as f32 * rect.height()
+}
+
+/// Maps a vertical position on the canvas back to a value.
+#[inline]
+pub fn y_to_value(rect: egui::Rect, min: f64, max: f64, y: f32) -> f64 {
+    let span = (max - min).max(f64::EPSILON);
+    min + ((rect.bottom() - y) / rect.height()) as f64 * span
+}
@@ -0,0 +1,164 @@
+use lyn_util::egui::LynId;
+
+use crate::{
+    app::helpers::WidgetId,
+    model::pattern::curve::{CurvePattern, CurvePoint},
+};
+
+use super::{
+    constants::{CURVE_CANVAS_HEIGHT, CURVE_POINT_RADIUS},
+    curve_canvas::{value_to_y, y_to_value},
+};
+
+#[derive(Debug)]
+#[must_use]
+pub struct CurvePointWidget<'pat> {
+    pattern: &'pat mut CurvePattern,
+    point: CurvePoint,
+    size_per_beat: f32,
+    ticks_per_beat: u64,
+    tick_snap: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CurvePointDragState {
+    pub orig_tick: u64,
+    pub orig_value: f64,
+}
+
+impl<'pat> CurvePointWidget<'pat> {
+    pub fn new(
+        pattern: &'pat mut CurvePattern,
+        point: CurvePoint,
+        size_per_beat: f32,
+        ticks_per_beat: u64,
+        tick_snap: u64,
+    ) -> Self {
+        Self {
+            pattern,
+            point,
+            size_per_beat,
+            ticks_per_beat,
+            tick_snap,
+        }
+    }
+
+    /// Calculate the rectangle of the whole curve canvas.
+    #[inline]
+    fn calc_canvas_rect(&self, anchor: egui::Pos2) -> egui::Rect {
+        egui::Rect::from_min_size(
+            anchor,
+            emath::vec2(
+                self.pattern.beats as f32 * self.size_per_beat,
+                CURVE_CANVAS_HEIGHT,
+            ),
+        )
+    }
+
+    /// Convert ticks to pixels based on the current `size_per_beat`.
+    #[inline]
+    fn ticks_to_pixels(&self, ticks: u64) -> f32 {
+        ticks as f32 / self.ticks_per_beat as f32 * self.size_per_beat
+    }
+
+    /// Convert pixels to ticks based on the current `size_per_beat`.
+    #[inline]
+    fn pixels_to_ticks(&self, pixels: f32) -> i64 {
+        (pixels / self.size_per_beat * self.ticks_per_beat as f32).round() as i64
+    }
+
+    /// Snap ticks to the nearest `tick_snap`.
+    #[inline]
+    fn snap_ticks(&self, ticks: i64) -> u64 {
+        let snap = self.tick_snap as i64;
+        ((ticks + snap / 2) / snap * snap).max(0) as u64
+    }
+}
+
+// LYN: Widget Impl
+
+impl<'pat> CurvePointWidget<'pat> {
+    pub fn show(self, ui: &mut egui::Ui) {
+        let canvas = self.calc_canvas_rect(ui.min_rect().left_top());
+        let (min, max) = (self.pattern.min, self.pattern.max);
+        let center = emath::pos2(
+            canvas.left() + self.ticks_to_pixels(self.point.tick),
+            value_to_y(canvas, min, max, self.point.value),
+        );
+        let rect = egui::Rect::from_center_size(center, egui::Vec2::splat(CURVE_POINT_RADIUS * 3.));
+
+        let id = self.point.id().into();
+        let resp = ui.interact(rect, id, egui::Sense::click_and_drag());
+
+        if resp.secondary_clicked() {
+            self.pattern.del_point(self.point.id());
+            return;
+        }
+
+        let selected_id = WidgetId::PatternEditorCurveSelectedPoint.into();
+        if resp.clicked() || resp.drag_started() {
+            ui.data_mut(|d| d.insert_temp(selected_id, self.point.id()));
+        }
+        let selected = ui
+            .data(|d| d.get_temp::<LynId>(selected_id))
+            .is_some_and(|id| id == self.point.id());
+
+        if resp.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+
+        if resp.drag_started() {
+            let drag_state = CurvePointDragState {
+                orig_tick: self.point.tick,
+                orig_value: self.point.value,
+            };
+            ui.data_mut(|d| d.insert_temp(id, drag_state));
+        }
+
+        if resp.dragged() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+
+            let drag_state: CurvePointDragState = ui.data(|d| d.get_temp(id)).unwrap_or_default();
+            let total_drag = ui.input(|i| {
+                i.pointer
+                    .press_origin()
+                    .map(|origin| i.pointer.interact_pos().unwrap_or(origin) - origin)
+                    .unwrap_or(egui::Vec2::ZERO)
+            });
+
+            let delta_ticks = self.pixels_to_ticks(total_drag.x);
+            let new_tick = self
+                .snap_ticks(drag_state.orig_tick as i64 + delta_ticks)
+                .min(self.pattern.beats * self.ticks_per_beat);
+            let orig_y = value_to_y(canvas, min, max, drag_state.orig_value);
+            let new_value = y_to_value(canvas, min, max, orig_y + total_drag.y).clamp(min, max);
+
+            self.pattern.edit_point(self.point.id(), |p| {
+                p.tick = new_tick;
+                p.value = new_value;
+            });
+        }
+
+        if resp.drag_stopped() {
+            ui.data_mut(|d| d.remove::<CurvePointDragState>(id));
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter();
+
+            let point_color = self.pattern.color;
+            let stroke_color = if selected || resp.hovered() || resp.dragged() {
+                ecolor::Color32::WHITE
+            } else {
+                point_color.lerp_to_gamma(ecolor::Color32::BLACK, 0.5)
+            };
+
+            painter.circle(
+                center,
+                CURVE_POINT_RADIUS,
+                point_color,
+                egui::Stroke::new(1.5, stroke_color),
+            );
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use lyn_util::egui::LynId;
+
+use self::{curve_canvas::CurveCanvas, curve_point::CurvePointWidget};
+use crate::{
+    app::{
+        helpers::WidgetId,
+        tools::pattern_editor::pattern_detail::{DETAIL_FIELD_WIDTH, PatternDetailFields},
+    },
+    model::{
+        pattern::{
+            SheetPatternTrait,
+            curve::{CurveInterp, CurvePattern},
+        },
+        state::CentralState,
+    },
+};
+
+pub mod constants;
+pub mod curve_canvas;
+pub mod curve_point;
+
+// LYN: Curve Editor State
+
+#[derive(Debug)]
+pub struct CurveEditor<'pat> {
+    curve_pattern: &'pat mut CurvePattern,
+    state: Arc<CentralState>,
+}
+
+impl<'pat> CurveEditor<'pat> {
+    pub fn new(curve_pattern: &'pat mut CurvePattern, state: Arc<CentralState>) -> Self {
+        Self {
+            curve_pattern,
+            state,
+        }
+    }
+}
+
+impl<'pat> CurveEditor<'pat> {
+    pub fn show_inside(mut self, ui: &mut egui::Ui) {
+        egui::SidePanel::right(WidgetId::PatternEditorCurveDetailPanel)
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                self.detail_panel(ui);
+            });
+
+        if !self.curve_pattern.usable() {
+            egui::TopBottomPanel::top(WidgetId::PatternEditorCurveNotificationBar)
+                .frame(egui::Frame::NONE.inner_margin(4.))
+                .show_inside(ui, |ui| {
+                    ui.colored_label(
+                        ui.style().visuals.error_fg_color,
+                        "请确保已通讯选择目标且标识不为空。",
+                    );
+                });
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.spacing_mut().item_spacing = emath::vec2(0., 0.);
+
+            let size_per_beat = *self.state.ui.pattern_editor_size_per_beat.read();
+            let ticks_per_beat = self.state.sheet_ticks_per_beat();
+            let tick_snap = self.state.sheet_tick_snap();
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                CurveCanvas::new(size_per_beat, ticks_per_beat, tick_snap, self.curve_pattern)
+                    .show(ui);
+
+                let points = self.curve_pattern.points_iter_owned().collect::<Vec<_>>();
+                for point in points {
+                    CurvePointWidget::new(
+                        self.curve_pattern,
+                        point,
+                        size_per_beat,
+                        ticks_per_beat,
+                        tick_snap,
+                    )
+                    .show(ui);
+                }
+            });
+        });
+    }
+}
+
+impl<'pat> CurveEditor<'pat> {
+    fn detail_panel(&mut self, ui: &mut egui::Ui) {
+        let width = DETAIL_FIELD_WIDTH;
+        let ticks_per_beat = self.state.sheet_ticks_per_beat();
+        let min_beats = self.curve_pattern.min_beats(ticks_per_beat);
+        PatternDetailFields {
+            icon: &mut self.curve_pattern.icon,
+            name: &mut self.curve_pattern.name,
+            color: &mut self.curve_pattern.color,
+            beats: &mut self.curve_pattern.beats,
+            min_beats,
+            tag: Some(&mut self.curve_pattern.tag),
+            target_id: &mut self.curve_pattern.target_id,
+            state: self.state.clone(),
+        }
+        .show(ui);
+
+        ui.horizontal(|ui| {
+            ui.label("上限：");
+            let min = self.curve_pattern.min;
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::DragValue::new(&mut self.curve_pattern.max)
+                    .range((min + f64::EPSILON)..=f64::MAX)
+                    .speed(0.01),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("下限：");
+            let max = self.curve_pattern.max;
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::DragValue::new(&mut self.curve_pattern.min)
+                    .range(f64::MIN..=(max - f64::EPSILON))
+                    .speed(0.01),
+            );
+        });
+
+        ui.separator();
+
+        let selected = ui
+            .data(|d| d.get_temp::<LynId>(WidgetId::PatternEditorCurveSelectedPoint.into()))
+            .and_then(|id| self.curve_pattern.get_point(id));
+        let Some(mut point) = selected else {
+            ui.disable();
+            ui.label("未选择节点");
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("位置：");
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::DragValue::new(&mut point.tick)
+                    .range(0..=self.curve_pattern.beats * ticks_per_beat),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("数值：");
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::DragValue::new(&mut point.value)
+                    .range(self.curve_pattern.min..=self.curve_pattern.max)
+                    .speed((self.curve_pattern.max - self.curve_pattern.min) / 100.),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("插值：");
+            egui::ComboBox::new(WidgetId::PatternEditorCurveComboBoxInterp, "")
+                .selected_text(point.interp.to_string())
+                .width(width)
+                .show_ui(ui, |ui| {
+                    for interp in CurveInterp::variants() {
+                        let same_kind =
+                            std::mem::discriminant(&point.interp) == std::mem::discriminant(interp);
+                        if ui.selectable_label(same_kind, interp.to_string()).clicked()
+                            && !same_kind
+                        {
+                            point.interp = *interp;
+                        }
+                    }
+                });
+        });
+
+        if let CurveInterp::Bezier { x1, y1, x2, y2 } = &mut point.interp {
+            for (label, value, range) in [
+                ("X1：", x1, 0.0..=1.0),
+                ("Y1：", y1, -1.0..=2.0),
+                ("X2：", x2, 0.0..=1.0),
+                ("Y2：", y2, -1.0..=2.0),
+            ] {
+                ui.horizontal(|ui| {
+                    ui.label(label);
+                    ui.add_sized(
+                        [width, ui.available_height()],
+                        egui::DragValue::new(value).range(range).speed(0.01),
+                    );
+                });
+            }
+        }
+
+        if self.curve_pattern.get_point(point.id()).is_some_and(|p| {
+            p.tick != point.tick || p.value != point.value || p.interp != point.interp
+        }) {
+            self.curve_pattern.edit_point(point.id(), |p| *p = point);
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub const EVENT_ROW_HEIGHT: f32 = 24.;
+pub const EVENT_LANE_MIN_ROWS: usize = 4;
+pub const EVENT_MARKER_WIDTH: f32 = 72.;
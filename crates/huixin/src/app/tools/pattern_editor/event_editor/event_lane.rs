@@ -0,0 +1,104 @@
+use lyn_util::comm::DataMap;
+
+use crate::{
+    app::helpers::WidgetId,
+    model::pattern::{
+        SheetPatternTrait,
+        event::{EventPattern, PatternEvent},
+    },
+};
+
+use super::constants::EVENT_ROW_HEIGHT;
+
+#[derive(Debug)]
+#[must_use]
+pub struct EventLane<'pat> {
+    size_per_beat: f32,
+    ticks_per_beat: u64,
+    tick_snap: u64,
+    rows: usize,
+    event_pattern: &'pat mut EventPattern,
+}
+
+impl<'pat> EventLane<'pat> {
+    pub fn new(
+        size_per_beat: f32,
+        ticks_per_beat: u64,
+        tick_snap: u64,
+        rows: usize,
+        event_pattern: &'pat mut EventPattern,
+    ) -> Self {
+        Self {
+            size_per_beat,
+            ticks_per_beat,
+            tick_snap,
+            rows,
+            event_pattern,
+        }
+    }
+
+    fn calc_tick(&self, x: f32) -> u64 {
+        let tick = ((x / self.size_per_beat) * self.ticks_per_beat as f32).floor() as u64;
+        tick / self.tick_snap * self.tick_snap
+    }
+}
+
+impl<'pat> EventLane<'pat> {
+    pub fn show(self, ui: &mut egui::Ui) {
+        let total_width = self.event_pattern.beats() as f32 * self.size_per_beat;
+        let desired_size = emath::vec2(total_width, self.rows as f32 * EVENT_ROW_HEIGHT);
+        let (rect, resp) = ui.allocate_exact_size(desired_size, egui::Sense::click_and_drag());
+
+        let visuals = ui.style().noninteractive();
+
+        if resp.clicked() {
+            let pos = resp.interact_pointer_pos().unwrap();
+            let tick = self
+                .calc_tick(pos.x - rect.left())
+                .min(self.event_pattern.beats * self.ticks_per_beat - 1);
+            let event = PatternEvent::new(tick, String::new(), DataMap::new());
+            ui.data_mut(|d| {
+                d.insert_temp(WidgetId::PatternEditorEventSelectedEvent.into(), event.id())
+            });
+            self.event_pattern.add_event(event);
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter_at(rect);
+
+            // background color
+            for row in 0..self.rows {
+                let y = rect.top() + row as f32 * EVENT_ROW_HEIGHT;
+                let row_rect = emath::Rect::from_min_max(
+                    emath::pos2(rect.left(), y),
+                    emath::pos2(rect.right(), y + EVENT_ROW_HEIGHT),
+                );
+                let row_color = if row % 2 == 0 {
+                    visuals.bg_fill.linear_multiply(1.5)
+                } else {
+                    visuals.bg_fill.linear_multiply(0.5)
+                };
+                painter.rect_filled(row_rect, 0., row_color);
+            }
+
+            // vertical lines
+            for tick in (0..=self.event_pattern.beats() * self.ticks_per_beat)
+                .step_by(self.tick_snap as usize)
+            {
+                let x =
+                    rect.left() + (tick as f32 / self.ticks_per_beat as f32) * self.size_per_beat;
+                painter.line_segment(
+                    [emath::pos2(x, rect.top()), emath::pos2(x, rect.bottom())],
+                    egui::Stroke::new(
+                        match (tick % (self.ticks_per_beat * 4), tick % self.ticks_per_beat) {
+                            (0, _) => 0.7,
+                            (_, 0) => 0.4,
+                            _ => 0.2,
+                        },
+                        visuals.fg_stroke.color.linear_multiply(0.5),
+                    ),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use lyn_util::egui::{LynId, text_color};
+
+use crate::{app::helpers::WidgetId, model::pattern::event::EventPattern};
+
+use super::constants::{EVENT_MARKER_WIDTH, EVENT_ROW_HEIGHT};
+
+#[derive(Debug)]
+#[must_use]
+pub struct EventMarkerWidget<'pat> {
+    pattern: &'pat mut EventPattern,
+    event_id: LynId,
+    tick: u64,
+    row: usize,
+    size_per_beat: f32,
+    ticks_per_beat: u64,
+    tick_snap: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct EventMarkerDragState {
+    pub orig_tick: u64,
+}
+
+impl<'pat> EventMarkerWidget<'pat> {
+    pub fn new(
+        pattern: &'pat mut EventPattern,
+        (event_id, tick): (LynId, u64),
+        row: usize,
+        size_per_beat: f32,
+        ticks_per_beat: u64,
+        tick_snap: u64,
+    ) -> Self {
+        Self {
+            pattern,
+            event_id,
+            tick,
+            row,
+            size_per_beat,
+            ticks_per_beat,
+            tick_snap,
+        }
+    }
+
+    /// Calculate the rectangle of the marker based on its `tick` and `row`.
+    #[inline]
+    fn calc_rect(&self, anchor: egui::Pos2) -> egui::Rect {
+        let min = egui::Pos2 {
+            x: anchor.x + self.ticks_to_pixels(self.tick),
+            y: anchor.y + self.row as f32 * EVENT_ROW_HEIGHT + 2.,
+        };
+        egui::Rect::from_min_size(min, emath::vec2(EVENT_MARKER_WIDTH, EVENT_ROW_HEIGHT - 4.))
+    }
+
+    /// Convert ticks to pixels based on the current `size_per_beat`.
+    #[inline]
+    fn ticks_to_pixels(&self, ticks: u64) -> f32 {
+        ticks as f32 / self.ticks_per_beat as f32 * self.size_per_beat
+    }
+
+    /// Convert pixels to ticks based on the current `size_per_beat`.
+    #[inline]
+    fn pixels_to_ticks(&self, pixels: f32) -> i64 {
+        (pixels / self.size_per_beat * self.ticks_per_beat as f32).round() as i64
+    }
+
+    /// Snap ticks to the nearest `tick_snap`.
+    #[inline]
+    fn snap_ticks(&self, ticks: i64) -> u64 {
+        let snap = self.tick_snap as i64;
+        ((ticks + snap / 2) / snap * snap).max(0) as u64
+    }
+}
+
+// LYN: Widget Impl
+
+impl<'pat> EventMarkerWidget<'pat> {
+    pub fn show(self, ui: &mut egui::Ui) {
+        let anchor = ui.min_rect().left_top();
+        let rect = self.calc_rect(anchor);
+
+        let id = self.event_id.into();
+        let resp = ui.interact(rect, id, egui::Sense::click_and_drag());
+
+        if resp.secondary_clicked() {
+            self.pattern.del_event(self.event_id);
+            return;
+        }
+
+        let selected_id = WidgetId::PatternEditorEventSelectedEvent.into();
+        if resp.clicked() || resp.drag_started() {
+            ui.data_mut(|d| d.insert_temp(selected_id, self.event_id));
+        }
+        let selected = ui
+            .data(|d| d.get_temp::<LynId>(selected_id))
+            .is_some_and(|id| id == self.event_id);
+
+        if resp.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+
+        if resp.drag_started() {
+            let drag_state = EventMarkerDragState {
+                orig_tick: self.tick,
+            };
+            ui.data_mut(|d| d.insert_temp(id, drag_state));
+        }
+
+        if resp.dragged() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+
+            let drag_state: EventMarkerDragState = ui.data(|d| d.get_temp(id)).unwrap_or_default();
+            let total_drag = ui.input(|i| {
+                i.pointer
+                    .press_origin()
+                    .map(|origin| i.pointer.interact_pos().unwrap_or(origin) - origin)
+                    .unwrap_or(egui::Vec2::ZERO)
+            });
+
+            let delta_ticks = self.pixels_to_ticks(total_drag.x);
+            let new_tick = self
+                .snap_ticks(drag_state.orig_tick as i64 + delta_ticks)
+                .min(self.pattern.beats * self.ticks_per_beat - 1);
+            self.pattern
+                .edit_event(self.event_id, |e| e.tick = new_tick);
+        }
+
+        if resp.drag_stopped() {
+            ui.data_mut(|d| d.remove::<EventMarkerDragState>(id));
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter();
+
+            let marker_color = self.pattern.color;
+            let stroke_color = if selected || resp.hovered() || resp.dragged() {
+                ecolor::Color32::WHITE
+            } else {
+                marker_color.lerp_to_gamma(ecolor::Color32::BLACK, 0.5)
+            };
+
+            painter.rect(
+                rect,
+                2.0,
+                marker_color,
+                egui::Stroke::new(1.0, stroke_color),
+                egui::StrokeKind::Middle,
+            );
+            painter.line_segment(
+                [rect.left_top(), rect.left_bottom()],
+                egui::Stroke::new(2.0, stroke_color),
+            );
+
+            let tag = self
+                .pattern
+                .get_event(self.event_id)
+                .map(|e| e.tag.as_str())
+                .filter(|tag| !tag.is_empty())
+                .unwrap_or("?");
+            ui.painter_at(rect).text(
+                rect.left_center() + emath::vec2(4., 0.),
+                egui::Align2::LEFT_CENTER,
+                tag,
+                egui::FontId::default(),
+                text_color(marker_color),
+            );
+        }
+    }
+}
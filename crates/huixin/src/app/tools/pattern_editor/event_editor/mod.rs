@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use lyn_util::egui::LynId;
+
+use self::{
+    constants::{EVENT_LANE_MIN_ROWS, EVENT_MARKER_WIDTH},
+    event_lane::EventLane,
+    event_marker::EventMarkerWidget,
+};
+use crate::{
+    app::{
+        helpers::WidgetId,
+        tools::pattern_editor::pattern_detail::{DETAIL_FIELD_WIDTH, PatternDetailFields},
+    },
+    model::{
+        pattern::{SheetPatternTrait, event::EventPattern},
+        state::CentralState,
+    },
+};
+
+pub mod constants;
+pub mod event_lane;
+pub mod event_marker;
+
+// LYN: Event Editor State
+
+/// `((event id, tick), row)` of every marker to draw.
+type MarkerLayout = Vec<((LynId, u64), usize)>;
+
+#[derive(Debug)]
+pub struct EventEditor<'pat> {
+    event_pattern: &'pat mut EventPattern,
+    state: Arc<CentralState>,
+}
+
+impl<'pat> EventEditor<'pat> {
+    pub fn new(event_pattern: &'pat mut EventPattern, state: Arc<CentralState>) -> Self {
+        Self {
+            event_pattern,
+            state,
+        }
+    }
+
+    /// Stacks markers into rows so that none of them overlap, returns the number of rows used.
+    fn layout_markers(&self, size_per_beat: f32, ticks_per_beat: u64) -> (MarkerLayout, usize) {
+        let mut events = self
+            .event_pattern
+            .events_iter()
+            .map(|e| (e.id(), e.tick))
+            .collect::<Vec<_>>();
+        events.sort_by_key(|(_, tick)| *tick);
+
+        let mut row_ends: Vec<f32> = Vec::new();
+        let placed = events
+            .into_iter()
+            .map(|(id, tick)| {
+                let x = tick as f32 / ticks_per_beat as f32 * size_per_beat;
+                let row = row_ends
+                    .iter()
+                    .position(|end| *end <= x)
+                    .unwrap_or(row_ends.len());
+                if row == row_ends.len() {
+                    row_ends.push(0.);
+                }
+                row_ends[row] = x + EVENT_MARKER_WIDTH;
+                ((id, tick), row)
+            })
+            .collect();
+        (placed, row_ends.len().max(EVENT_LANE_MIN_ROWS))
+    }
+}
+
+impl<'pat> EventEditor<'pat> {
+    pub fn show_inside(mut self, ui: &mut egui::Ui) {
+        egui::SidePanel::right(WidgetId::PatternEditorEventDetailPanel)
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.detail_panel(ui);
+                });
+            });
+
+        if !self.event_pattern.usable() {
+            egui::TopBottomPanel::top(WidgetId::PatternEditorEventNotificationBar)
+                .frame(egui::Frame::NONE.inner_margin(4.))
+                .show_inside(ui, |ui| {
+                    ui.colored_label(
+                        ui.style().visuals.error_fg_color,
+                        "请确保已选择通讯目标且所有事件的标识不为空。",
+                    );
+                });
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.spacing_mut().item_spacing = emath::vec2(0., 0.);
+
+            let size_per_beat = *self.state.ui.pattern_editor_size_per_beat.read();
+            let ticks_per_beat = self.state.sheet_ticks_per_beat();
+            let tick_snap = self.state.sheet_tick_snap();
+            let (markers, rows) = self.layout_markers(size_per_beat, ticks_per_beat);
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                EventLane::new(
+                    size_per_beat,
+                    ticks_per_beat,
+                    tick_snap,
+                    rows,
+                    self.event_pattern,
+                )
+                .show(ui);
+
+                for (event, row) in markers {
+                    EventMarkerWidget::new(
+                        self.event_pattern,
+                        event,
+                        row,
+                        size_per_beat,
+                        ticks_per_beat,
+                        tick_snap,
+                    )
+                    .show(ui);
+                }
+            });
+        });
+    }
+}
+
+impl<'pat> EventEditor<'pat> {
+    fn detail_panel(&mut self, ui: &mut egui::Ui) {
+        let width = DETAIL_FIELD_WIDTH;
+        let ticks_per_beat = self.state.sheet_ticks_per_beat();
+        let min_beats = self.event_pattern.min_beats(ticks_per_beat);
+        PatternDetailFields {
+            icon: &mut self.event_pattern.icon,
+            name: &mut self.event_pattern.name,
+            color: &mut self.event_pattern.color,
+            beats: &mut self.event_pattern.beats,
+            min_beats,
+            tag: None,
+            target_id: &mut self.event_pattern.target_id,
+            state: self.state.clone(),
+        }
+        .show(ui);
+
+        ui.separator();
+
+        let selected = ui
+            .data(|d| d.get_temp::<LynId>(WidgetId::PatternEditorEventSelectedEvent.into()))
+            .and_then(|id| self.event_pattern.get_event(id))
+            .cloned();
+        let Some(mut event) = selected else {
+            ui.disable();
+            ui.label("未选择事件");
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("位置：");
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::DragValue::new(&mut event.tick)
+                    .range(0..=self.event_pattern.beats * ticks_per_beat - 1),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("标识：");
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::TextEdit::singleline(&mut event.tag),
+            );
+        });
+
+        ui.label("负载：")
+            .on_hover_text("值按 JSON 解析，解析失败时作为字符串发送");
+
+        let event_id = event.id();
+        let mut renamed = None;
+        let mut removed = None;
+        egui::Grid::new(WidgetId::PatternEditorEventPayloadGrid)
+            .num_columns(3)
+            .spacing(emath::vec2(2., 2.))
+            .show(ui, |ui| {
+                for (key, value) in event.data.iter_mut() {
+                    let key_buf_id = egui::Id::new((event_id, key.as_str(), "key"));
+                    let mut key_text = ui
+                        .data(|d| d.get_temp::<String>(key_buf_id))
+                        .unwrap_or_else(|| key.clone());
+                    let key_resp = ui.add_sized(
+                        [width / 2., ui.available_height()],
+                        egui::TextEdit::singleline(&mut key_text),
+                    );
+                    if key_resp.has_focus() {
+                        ui.data_mut(|d| d.insert_temp(key_buf_id, key_text.clone()));
+                    } else {
+                        ui.data_mut(|d| d.remove::<String>(key_buf_id));
+                    }
+                    if key_resp.lost_focus() && !key_text.is_empty() && key_text != *key {
+                        renamed = Some((key.clone(), key_text));
+                    }
+
+                    let value_buf_id = egui::Id::new((event_id, key.as_str(), "value"));
+                    let mut value_text = ui
+                        .data(|d| d.get_temp::<String>(value_buf_id))
+                        .unwrap_or_else(|| value_to_text(value));
+                    let value_resp = ui.add_sized(
+                        [width / 2., ui.available_height()],
+                        egui::TextEdit::singleline(&mut value_text),
+                    );
+                    if value_resp.has_focus() {
+                        ui.data_mut(|d| d.insert_temp(value_buf_id, value_text.clone()));
+                    } else {
+                        ui.data_mut(|d| d.remove::<String>(value_buf_id));
+                    }
+                    if value_resp.changed() {
+                        *value = text_to_value(&value_text);
+                    }
+
+                    if ui.button(" ").clicked() {
+                        removed = Some(key.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some((old_key, new_key)) = renamed
+            && !event.data.contains_key(&new_key)
+            && let Some(value) = event.data.remove(&old_key)
+        {
+            event.data.insert(new_key, value);
+        }
+        if let Some(key) = removed {
+            event.data.remove(&key);
+        }
+        if ui.button("新增字段").clicked() {
+            let key = (0..)
+                .map(|i| format!("key{i}"))
+                .find(|key| !event.data.contains_key(key))
+                .unwrap();
+            event.data.insert(key, json::Value::Null);
+        }
+
+        if self
+            .event_pattern
+            .get_event(event_id)
+            .is_some_and(|e| *e != event)
+        {
+            self.event_pattern.edit_event(event_id, |e| *e = event);
+        }
+    }
+}
+
+// LYN: Helpers
+
+fn value_to_text(value: &json::Value) -> String {
+    match value {
+        json::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn text_to_value(text: &str) -> json::Value {
+    json::from_str(text).unwrap_or_else(|_| json::Value::String(text.to_string()))
+}
@@ -0,0 +1,128 @@
+use crate::{
+    app::{helpers::WidgetId, tools::pattern_editor::pattern_detail::DETAIL_FIELD_WIDTH},
+    model::pattern::mapping::{ConstantField, FieldRule, InstructionMapping, NoteRoute},
+};
+
+// LYN: Mapping Editor
+
+/// Edits the rules rewriting the instructions of a pattern, inside its detail panel.
+#[derive(Debug)]
+#[must_use]
+pub struct MappingEditor<'pat> {
+    mapping: &'pat mut InstructionMapping,
+}
+
+impl<'pat> MappingEditor<'pat> {
+    pub fn new(mapping: &'pat mut InstructionMapping) -> Self {
+        Self { mapping }
+    }
+
+    pub fn show(self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("指令映射")
+            .id_salt(WidgetId::PatternEditorMappingEditor)
+            .show(ui, |ui| {
+                Self::routes(ui, &mut self.mapping.routes);
+                ui.separator();
+                Self::fields(ui, &mut self.mapping.fields);
+                ui.separator();
+                Self::constants(ui, &mut self.mapping.constants);
+            });
+    }
+}
+
+impl MappingEditor<'_> {
+    fn routes(ui: &mut egui::Ui, routes: &mut Vec<NoteRoute>) {
+        ui.label("分流：")
+            .on_hover_text("音高在范围内的音符改用指定的标识发送，按顺序取第一个匹配项");
+        let mut removed = None;
+        for (i, route) in routes.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut route.low).range(0..=127));
+                ui.label("~");
+                ui.add(egui::DragValue::new(&mut route.high).range(route.low..=127));
+                if ui.button(" ").clicked() {
+                    removed = Some(i);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("标识：");
+                ui.add_sized(
+                    [DETAIL_FIELD_WIDTH, ui.available_height()],
+                    egui::TextEdit::singleline(&mut route.tag),
+                );
+            });
+        }
+        if let Some(i) = removed {
+            routes.remove(i);
+        }
+        if ui.button("新增分流").clicked() {
+            routes.push(NoteRoute::default());
+        }
+    }
+
+    fn fields(ui: &mut egui::Ui, fields: &mut Vec<FieldRule>) {
+        ui.label("字段：")
+            .on_hover_text("重命名字段（留空则保留原名），数值变为 值 × 倍率 + 偏移");
+        let mut removed = None;
+        for (i, rule) in fields.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add_sized(
+                    [DETAIL_FIELD_WIDTH / 2., ui.available_height()],
+                    egui::TextEdit::singleline(&mut rule.key).hint_text("字段"),
+                );
+                ui.label("→");
+                ui.add_sized(
+                    [DETAIL_FIELD_WIDTH / 2., ui.available_height()],
+                    egui::TextEdit::singleline(&mut rule.rename).hint_text("新名称"),
+                );
+                if ui.button(" ").clicked() {
+                    removed = Some(i);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("×");
+                ui.add(
+                    egui::DragValue::new(&mut rule.scale)
+                        .speed(0.01)
+                        .max_decimals(8),
+                );
+                ui.label("+");
+                ui.add(egui::DragValue::new(&mut rule.offset).speed(0.01));
+            });
+        }
+        if let Some(i) = removed {
+            fields.remove(i);
+        }
+        if ui.button("新增字段规则").clicked() {
+            fields.push(FieldRule::default());
+        }
+    }
+
+    fn constants(ui: &mut egui::Ui, constants: &mut Vec<ConstantField>) {
+        ui.label("常量：")
+            .on_hover_text("附加到每条指令的字段，值按 JSON 解析，解析失败时作为字符串发送");
+        let mut removed = None;
+        for (i, constant) in constants.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add_sized(
+                    [DETAIL_FIELD_WIDTH / 2., ui.available_height()],
+                    egui::TextEdit::singleline(&mut constant.key).hint_text("字段"),
+                );
+                ui.label("=");
+                ui.add_sized(
+                    [DETAIL_FIELD_WIDTH / 2., ui.available_height()],
+                    egui::TextEdit::singleline(&mut constant.value).hint_text("值"),
+                );
+                if ui.button(" ").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            constants.remove(i);
+        }
+        if ui.button("新增常量").clicked() {
+            constants.push(ConstantField::default());
+        }
+    }
+}
@@ -1,10 +1,7 @@
 use either::Either;
 
 use super::constants::KEY_ROW_HEIGHT;
-use crate::{
-    model::pattern::midi::{MidiNote, MidiPattern},
-    routines::metronome::TICK_PER_BEAT,
-};
+use crate::model::pattern::midi::{MidiNote, MidiPattern};
 
 pub const RESIZE_HANDLE_WIDTH: f32 = 6.;
 
@@ -14,6 +11,7 @@ pub struct MidiNoteWidget<'pat> {
     pattern: &'pat mut MidiPattern,
     note: MidiNote,
     size_per_beat: f32,
+    ticks_per_beat: u64,
     tick_snap: u64,
 }
 
@@ -36,12 +34,14 @@ impl<'pat> MidiNoteWidget<'pat> {
         pattern: &'pat mut MidiPattern,
         note: MidiNote,
         size_per_beat: f32,
+        ticks_per_beat: u64,
         tick_snap: u64,
     ) -> Self {
         Self {
             pattern,
             note,
             size_per_beat,
+            ticks_per_beat,
             tick_snap,
         }
     }
@@ -63,13 +63,13 @@ impl<'pat> MidiNoteWidget<'pat> {
     /// Convert ticks to pixels based on the current `size_per_beat`.
     #[inline]
     fn ticks_to_pixels(&self, ticks: u64) -> f32 {
-        ticks as f32 / TICK_PER_BEAT as f32 * self.size_per_beat
+        ticks as f32 / self.ticks_per_beat as f32 * self.size_per_beat
     }
 
     /// Convert pixels to ticks based on the current `size_per_beat`.
     #[inline]
     fn pixels_to_ticks(&self, pixels: f32) -> i64 {
-        (pixels / self.size_per_beat * TICK_PER_BEAT as f32).round() as i64
+        (pixels / self.size_per_beat * self.ticks_per_beat as f32).round() as i64
     }
 
     /// Snap ticks to the nearest `tick_snap`.
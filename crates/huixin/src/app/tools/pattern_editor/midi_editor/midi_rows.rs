@@ -1,9 +1,6 @@
-use crate::{
-    model::pattern::{
-        SheetPatternTrait,
-        midi::{MidiNote, MidiPattern},
-    },
-    routines::metronome::TICK_PER_BEAT,
+use crate::model::pattern::{
+    SheetPatternTrait,
+    midi::{MidiNote, MidiPattern},
 };
 
 use super::constants::{KEY_ROW_HEIGHT, NUMBER_OF_KEYS};
@@ -12,13 +9,22 @@ use super::constants::{KEY_ROW_HEIGHT, NUMBER_OF_KEYS};
 #[must_use]
 pub struct MidiRows<'pat> {
     size_per_beat: f32,
+    ticks_per_beat: u64,
+    tick_snap: u64,
     midi_pattern: &'pat mut MidiPattern,
 }
 
 impl<'pat> MidiRows<'pat> {
-    pub fn new(size_per_beat: f32, midi_pattern: &'pat mut MidiPattern) -> Self {
+    pub fn new(
+        size_per_beat: f32,
+        ticks_per_beat: u64,
+        tick_snap: u64,
+        midi_pattern: &'pat mut MidiPattern,
+    ) -> Self {
         Self {
             size_per_beat,
+            ticks_per_beat,
+            tick_snap,
             midi_pattern,
         }
     }
@@ -28,7 +34,8 @@ impl<'pat> MidiRows<'pat> {
     }
 
     fn calc_tick(&self, x: f32) -> u64 {
-        ((x / self.size_per_beat) * TICK_PER_BEAT as f32).floor() as u64
+        let tick = ((x / self.size_per_beat) * self.ticks_per_beat as f32).floor() as u64;
+        tick / self.tick_snap * self.tick_snap
     }
 }
 
@@ -44,8 +51,12 @@ impl<'pat> MidiRows<'pat> {
             let pos = resp.interact_pointer_pos().unwrap();
             let midicode = self.calc_midicode(pos.y - rect.top());
             let start = self.calc_tick(pos.x - rect.left());
-            self.midi_pattern
-                .add_note(MidiNote::new(midicode, u16::MAX, start, TICK_PER_BEAT));
+            self.midi_pattern.add_note(MidiNote::new(
+                midicode,
+                u16::MAX,
+                start,
+                self.ticks_per_beat,
+            ));
         }
 
         if ui.is_rect_visible(rect) {
@@ -67,16 +78,19 @@ impl<'pat> MidiRows<'pat> {
             }
 
             // vertical lines
-            for tick in 0..=self.midi_pattern.beats() * TICK_PER_BEAT {
-                let x = rect.left() + (tick as f32 / TICK_PER_BEAT as f32) * self.size_per_beat;
+            for tick in (0..=self.midi_pattern.beats() * self.ticks_per_beat)
+                .step_by(self.tick_snap as usize)
+            {
+                let x =
+                    rect.left() + (tick as f32 / self.ticks_per_beat as f32) * self.size_per_beat;
                 let line_start = emath::pos2(x, rect.top());
                 let line_end = emath::pos2(x, rect.bottom());
                 painter.line_segment(
                     [line_start, line_end],
                     egui::Stroke::new(
-                        match tick % (TICK_PER_BEAT * 4) {
-                            0 => 0.7,
-                            4 | 8 | 12 => 0.4,
+                        match (tick % (self.ticks_per_beat * 4), tick % self.ticks_per_beat) {
+                            (0, _) => 0.7,
+                            (_, 0) => 0.4,
                             _ => 0.2,
                         },
                         visuals.fg_stroke.color.linear_multiply(0.5),
@@ -1,16 +1,21 @@
 use std::sync::Arc;
 
-use egui_winit::clipboard::Clipboard;
-use lyn_util::egui::{copy_color, parse_color};
-
 use self::{midi_keyboard::MidiKeyboard, midi_note::MidiNoteWidget, midi_rows::MidiRows};
 use crate::{
-    app::helpers::WidgetId,
+    app::{
+        helpers::WidgetId,
+        tools::pattern_editor::{
+            mapping_editor::MappingEditor,
+            pattern_detail::{DETAIL_FIELD_WIDTH, PatternDetailFields},
+        },
+    },
     model::{
-        pattern::{SheetPatternTrait, midi::MidiPattern},
+        pattern::{
+            SheetPatternTrait,
+            midi::{MidiNoteMode, MidiPattern},
+        },
         state::CentralState,
     },
-    routines::metronome::TICK_PER_BEAT,
 };
 
 pub mod constants;
@@ -60,8 +65,11 @@ impl<'pat> MidiEditor<'pat> {
                 MidiKeyboard.show(ui);
 
                 let size_per_beat = *self.state.ui.pattern_editor_size_per_beat.read();
+                let ticks_per_beat = self.state.sheet_ticks_per_beat();
+                let tick_snap = self.state.sheet_tick_snap();
                 egui::ScrollArea::horizontal().show(ui, |ui| {
-                    MidiRows::new(size_per_beat, self.midi_pattern).show(ui);
+                    MidiRows::new(size_per_beat, ticks_per_beat, tick_snap, self.midi_pattern)
+                        .show(ui);
 
                     let notes = self.midi_pattern.notes_iter_owned().collect::<Vec<_>>();
                     for note in notes {
@@ -69,7 +77,8 @@ impl<'pat> MidiEditor<'pat> {
                             self.midi_pattern,
                             note,
                             size_per_beat,
-                            TICK_PER_BEAT / 4,
+                            ticks_per_beat,
+                            tick_snap,
                         )
                         .show(ui);
                     }
@@ -81,93 +90,39 @@ impl<'pat> MidiEditor<'pat> {
 
 impl<'pat> MidiEditor<'pat> {
     fn detail_panel(&mut self, ui: &mut egui::Ui) {
-        let width = 95.;
-        ui.horizontal(|ui| {
-            ui.label("图标：");
-            ui.add_sized(
-                [width, ui.available_height()],
-                egui::TextEdit::singleline(&mut self.midi_pattern.icon).char_limit(2),
-            );
-        });
-
-        ui.horizontal(|ui| {
-            ui.label("名称：");
-            ui.add_sized(
-                [width, ui.available_height()],
-                egui::TextEdit::singleline(&mut self.midi_pattern.name),
-            );
-        });
-
-        ui.horizontal(|ui| {
-            ui.label("颜色：");
-            egui::color_picker::color_edit_button_srgba(
-                ui,
-                &mut self.midi_pattern.color,
-                egui::color_picker::Alpha::Opaque,
-            );
-            if ui.button("󰆏 ").clicked() {
-                copy_color(self.midi_pattern.color);
-            }
-            if ui.button("󰆒 ").clicked()
-                && let Some(text) = Clipboard::new(None).get()
-                && let Some(color) = parse_color(text)
-            {
-                self.midi_pattern.color = color;
-            };
-        });
-
-        ui.horizontal(|ui| {
-            ui.label("长度：");
-            let min_beats = self.midi_pattern.min_beats();
-            ui.add_sized(
-                [width, ui.available_height()],
-                egui::DragValue::new(&mut self.midi_pattern.beats)
-                    .range(min_beats..=(u64::MAX >> 2)),
-            );
-        });
-
-        ui.horizontal(|ui| {
-            ui.label("标识：");
-            ui.add_sized(
-                [width, ui.available_height()],
-                egui::TextEdit::singleline(&mut self.midi_pattern.tag),
-            );
-        });
+        let min_beats = self
+            .midi_pattern
+            .min_beats(self.state.sheet_ticks_per_beat());
+        PatternDetailFields {
+            icon: &mut self.midi_pattern.icon,
+            name: &mut self.midi_pattern.name,
+            color: &mut self.midi_pattern.color,
+            beats: &mut self.midi_pattern.beats,
+            min_beats,
+            tag: Some(&mut self.midi_pattern.tag),
+            target_id: &mut self.midi_pattern.target_id,
+            state: self.state.clone(),
+        }
+        .show(ui);
 
         ui.horizontal(|ui| {
-            ui.label("目标：");
-
-            let target_name = self
-                .midi_pattern
-                .target_id
-                .as_ref()
-                .and_then(|id| self.state.sheet_get_comm_target(id))
-                .map(|target| target.read().name.clone());
-            if target_name.is_none() {
-                self.midi_pattern.target_id = None;
-            }
-            let target_name = {
-                let name = target_name.unwrap_or("未选择".to_string());
-                let chars = name.chars();
-                if chars.clone().count() <= 4 {
-                    name
-                } else {
-                    name.chars().take(3).chain("…".chars()).collect()
-                }
-            };
-            egui::ComboBox::new(WidgetId::PatternEditorMidiComboBoxCommTarget, "")
-                .selected_text(target_name)
-                .width(width)
+            ui.label("音符：");
+            egui::ComboBox::new(WidgetId::PatternEditorMidiComboBoxNoteMode, "")
+                .selected_text(self.midi_pattern.note_mode.to_string())
+                .width(DETAIL_FIELD_WIDTH)
                 .show_ui(ui, |ui| {
-                    let target_id_mut = &mut self.midi_pattern.target_id;
-                    for entry in self.state.sheet_comm_targets_iter() {
+                    for mode in MidiNoteMode::variants() {
                         ui.selectable_value(
-                            target_id_mut,
-                            Some(entry.key().clone()),
-                            &entry.value().read().name,
+                            &mut self.midi_pattern.note_mode,
+                            *mode,
+                            mode.to_string(),
                         );
                     }
                 })
+                .response
+                .on_hover_text("松开指令与按下指令使用相同的标签，其力度为 0");
         });
+
+        MappingEditor::new(&mut self.midi_pattern.mapping).show(ui);
     }
 }
@@ -3,12 +3,21 @@ use std::{ops::DerefMut, sync::Arc};
 use crate::{
     app::{
         helpers::WidgetId,
-        tools::{ToolWindow, ToolWindowId, pattern_editor::midi_editor::MidiEditor},
+        tools::{
+            ToolWindow, ToolWindowId,
+            pattern_editor::{
+                curve_editor::CurveEditor, event_editor::EventEditor, midi_editor::MidiEditor,
+            },
+        },
     },
     model::{pattern::SheetPattern, state::CentralState},
 };
 
+mod curve_editor;
+mod event_editor;
+mod mapping_editor;
 mod midi_editor;
+mod pattern_detail;
 
 #[derive(Debug)]
 pub struct PatternEditor {
@@ -67,10 +76,17 @@ impl ToolWindow for PatternEditor {
                     return;
                 };
 
+                self.state.history_touch_pattern(&pat.id);
                 match pat.write().deref_mut() {
                     SheetPattern::Midi(pat) => {
                         MidiEditor::new(pat, self.state.clone()).show_inside(ui)
                     }
+                    SheetPattern::Curve(pat) => {
+                        CurveEditor::new(pat, self.state.clone()).show_inside(ui)
+                    }
+                    SheetPattern::Event(pat) => {
+                        EventEditor::new(pat, self.state.clone()).show_inside(ui)
+                    }
                 };
             });
         self.open = open;
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use egui_winit::clipboard::Clipboard;
+use lyn_util::egui::{copy_color, parse_color};
+
+use crate::{
+    app::helpers::WidgetId,
+    model::state::{CentralState, Sheet, TargetId},
+};
+
+pub const DETAIL_FIELD_WIDTH: f32 = 95.;
+
+// LYN: Pattern Detail Fields
+
+/// Fields shared by the detail panels of every pattern editor.
+#[derive(Debug)]
+#[must_use]
+pub struct PatternDetailFields<'pat> {
+    pub icon: &'pat mut String,
+    pub name: &'pat mut String,
+    pub color: &'pat mut ecolor::Color32,
+    pub beats: &'pat mut u64,
+    pub min_beats: u64,
+    /// `None` for patterns whose tag is not decided on pattern level
+    pub tag: Option<&'pat mut String>,
+    pub target_id: &'pat mut Option<TargetId>,
+    pub state: Arc<CentralState>,
+}
+
+impl<'pat> PatternDetailFields<'pat> {
+    pub fn show(self, ui: &mut egui::Ui) {
+        let width = DETAIL_FIELD_WIDTH;
+        ui.horizontal(|ui| {
+            ui.label("图标：");
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::TextEdit::singleline(self.icon).char_limit(2),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("名称：");
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::TextEdit::singleline(self.name),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("颜色：");
+            egui::color_picker::color_edit_button_srgba(
+                ui,
+                self.color,
+                egui::color_picker::Alpha::Opaque,
+            );
+            if ui.button("󰆏 ").clicked() {
+                copy_color(*self.color);
+            }
+            if ui.button("󰆒 ").clicked()
+                && let Some(text) = Clipboard::new(None).get()
+                && let Some(color) = parse_color(text)
+            {
+                *self.color = color;
+            };
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("长度：");
+            ui.add_sized(
+                [width, ui.available_height()],
+                egui::DragValue::new(self.beats).range(self.min_beats..=Sheet::MAX_BEATS),
+            );
+        });
+
+        if let Some(tag) = self.tag {
+            ui.horizontal(|ui| {
+                ui.label("标识：");
+                ui.add_sized(
+                    [width, ui.available_height()],
+                    egui::TextEdit::singleline(tag),
+                );
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("目标：");
+
+            let target_name = self
+                .target_id
+                .as_ref()
+                .and_then(|id| self.state.sheet_get_comm_target(id))
+                .map(|target| target.read().name.clone());
+            if target_name.is_none() {
+                *self.target_id = None;
+            }
+            let target_name = {
+                let name = target_name.unwrap_or("未选择".to_string());
+                let chars = name.chars();
+                if chars.clone().count() <= 4 {
+                    name
+                } else {
+                    name.chars().take(3).chain("…".chars()).collect()
+                }
+            };
+            egui::ComboBox::new(WidgetId::PatternEditorComboBoxCommTarget, "")
+                .selected_text(target_name)
+                .width(width)
+                .show_ui(ui, |ui| {
+                    for entry in self.state.sheet_comm_targets_iter() {
+                        ui.selectable_value(
+                            self.target_id,
+                            Some(entry.key().clone()),
+                            &entry.value().read().name,
+                        );
+                    }
+                })
+        });
+    }
+}
@@ -0,0 +1,196 @@
+use std::{sync::Arc, time::Duration};
+
+use log::warn;
+
+use crate::{
+    app::{
+        helpers::WidgetId,
+        tools::{ToolWindow, ToolWindowId},
+    },
+    model::{
+        persistence::write_file_atomic,
+        state::CentralState,
+        traffic::{self, TrafficEntry, TrafficKind},
+    },
+};
+
+const TARGET_COLUMN_WIDTH: f32 = 100.;
+
+#[derive(Debug)]
+pub struct TrafficInspector {
+    open: bool,
+    state: Arc<CentralState>,
+    filter: String,
+    show_instructions: bool,
+    show_connections: bool,
+}
+
+impl TrafficInspector {
+    pub fn new(state: Arc<CentralState>) -> Self {
+        Self {
+            open: false,
+            state,
+            filter: String::new(),
+            show_instructions: true,
+            show_connections: true,
+        }
+    }
+}
+
+impl ToolWindow for TrafficInspector {
+    fn tool_id(&self) -> ToolWindowId {
+        ToolWindowId::TrafficInspector
+    }
+    fn icon(&self) -> String {
+        "󰍉 ".to_string()
+    }
+
+    fn window_open(&self) -> bool {
+        self.open
+    }
+
+    fn window_open_mut(&mut self) -> &mut bool {
+        &mut self.open
+    }
+
+    fn toggle_open(&mut self, open: Option<bool>) {
+        if let Some(open) = open {
+            self.open = open;
+        } else {
+            self.open = !self.open;
+        }
+        self.state.traffic_log_mut().set_inspected(self.open);
+    }
+    fn draw(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("流量监视")
+            .id(WidgetId::TrafficInspector.into())
+            .collapsible(true)
+            .open(&mut open)
+            .min_size(emath::vec2(400., 150.))
+            .default_size(emath::vec2(640., 320.))
+            .show(ctx, |ui| {
+                self.top_bar(ui);
+                ui.separator();
+                self.entries(ui);
+            });
+        self.open = open;
+        self.state.traffic_log_mut().set_inspected(open);
+        // new entries arrive from the writers
+        ctx.request_repaint_after(Duration::from_millis(100));
+    }
+}
+
+impl TrafficInspector {
+    fn top_bar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let paused = self.state.traffic_log().paused();
+            if ui
+                .button(if paused { "继续" } else { "暂停" })
+                .on_hover_text("暂停时不再记录新的流量")
+                .clicked()
+            {
+                self.state.traffic_log_mut().set_paused(!paused);
+            }
+            if ui.button("清空").clicked() {
+                self.state.traffic_log_mut().clear();
+            }
+            if ui
+                .button("导出")
+                .on_hover_text("将筛选出的记录导出为 JSON Lines 文件")
+                .clicked()
+            {
+                self.export();
+            }
+            ui.separator();
+            ui.checkbox(&mut self.show_instructions, "指令");
+            ui.checkbox(&mut self.show_connections, "连接事件");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter)
+                    .hint_text("筛选目标、标识或内容")
+                    .desired_width(160.),
+            );
+        });
+    }
+
+    fn entries(&self, ui: &mut egui::Ui) {
+        // copied out, so the writers are never held up by drawing
+        let shown = self.shown_entries();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::both()
+            .auto_shrink(false)
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, shown.len(), |ui, range| {
+                for entry in shown[range].iter() {
+                    Self::entry_row(ui, entry);
+                }
+            });
+    }
+
+    fn entry_row(ui: &mut egui::Ui, entry: &TrafficEntry) {
+        ui.horizontal(|ui| {
+            ui.monospace(format!("{:>10.3}", entry.time));
+            match &entry.kind {
+                TrafficKind::Instruction {
+                    tick,
+                    tag,
+                    payload,
+                    delivered,
+                } => {
+                    ui.monospace(format!("{tick:>7}"));
+                    ui.add_sized(
+                        [TARGET_COLUMN_WIDTH, ui.available_height()],
+                        egui::Label::new(entry.target.as_str()).truncate(),
+                    );
+                    let text = egui::RichText::new(format!("{tag} {payload}")).monospace();
+                    if *delivered {
+                        ui.label(text);
+                    } else {
+                        ui.label(text.color(ui.visuals().error_fg_color))
+                            .on_hover_text("未能送达");
+                    }
+                }
+                TrafficKind::Connection { message } => {
+                    ui.monospace(format!("{:>7}", "-"));
+                    ui.add_sized(
+                        [TARGET_COLUMN_WIDTH, ui.available_height()],
+                        egui::Label::new(entry.target.as_str()).truncate(),
+                    );
+                    ui.label(egui::RichText::new(message).color(ui.visuals().warn_fg_color));
+                }
+            }
+        });
+    }
+
+    fn shown_entries(&self) -> Vec<TrafficEntry> {
+        self.state
+            .traffic_log()
+            .entries()
+            .iter()
+            .filter(|entry| match entry.kind {
+                TrafficKind::Instruction { .. } => self.show_instructions,
+                TrafficKind::Connection { .. } => self.show_connections,
+            })
+            .filter(|entry| entry.matches(&self.filter))
+            .cloned()
+            .collect()
+    }
+
+    fn export(&self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON Lines", &["jsonl"])
+            .set_file_name("traffic.jsonl")
+            .save_file()
+        else {
+            return;
+        };
+        let mut content = Vec::new();
+        let result = traffic::write_entries(self.shown_entries().iter(), &mut content)
+            .and_then(|()| write_file_atomic(&path, content));
+        if let Err(e) = result {
+            warn!("Failed to export traffic to file {:?}: {}", path, e);
+            self.state
+                .app_set_notice_msg(Some(format!("无法导出到文件 {}：{e}", path.display())));
+        }
+    }
+}
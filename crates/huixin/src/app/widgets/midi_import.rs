@@ -0,0 +1,105 @@
+use crate::{
+    app::helpers::WidgetId,
+    model::{pattern::SheetPattern, smf::SmfImport, state::CentralState},
+};
+
+/// Lets the user pick which tracks/channels of a parsed MIDI file become patterns.
+#[derive(Debug)]
+pub struct MidiImportDialog {
+    file_name: String,
+    ticks_per_beat: u64,
+    import: SmfImport,
+    selected: Vec<bool>,
+    use_tempo: bool,
+}
+
+impl MidiImportDialog {
+    pub fn new(file_name: String, ticks_per_beat: u64, import: SmfImport) -> Self {
+        let selected = vec![true; import.groups.len()];
+        let use_tempo = import.bpm.is_some();
+        Self {
+            file_name,
+            ticks_per_beat,
+            import,
+            selected,
+            use_tempo,
+        }
+    }
+
+    /// Imports the selected tracks (and tempo) as one undo step.
+    fn apply(&self, state: &CentralState) {
+        state.history_transaction(|| {
+            for (group, _) in self
+                .import
+                .groups
+                .iter()
+                .zip(self.selected.iter())
+                .filter(|(_, selected)| **selected)
+            {
+                state.sheet_insert_pattern(SheetPattern::Midi(
+                    group.to_pattern(self.ticks_per_beat),
+                ));
+            }
+            if self.use_tempo
+                && let Some(bpm) = self.import.bpm
+            {
+                *state.sheet_bpm_mut() = bpm;
+            }
+        });
+    }
+}
+
+// LYN: Widget Impl
+
+impl MidiImportDialog {
+    /// Returns `true` once the dialog is done, either imported or cancelled.
+    pub fn draw(&mut self, ctx: &egui::Context, state: &CentralState) -> bool {
+        egui::Modal::new(WidgetId::MidiImportModal.into())
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new("导入 MIDI").heading().strong());
+                ui.label(egui::RichText::new(&self.file_name).weak());
+                ui.separator();
+
+                ui.label("每个勾选的轨道/通道将成为一个 MIDI 片段：");
+                egui::ScrollArea::vertical()
+                    .max_height(240.)
+                    .show(ui, |ui| {
+                        for (group, selected) in
+                            self.import.groups.iter().zip(self.selected.iter_mut())
+                        {
+                            ui.checkbox(
+                                selected,
+                                format!("{}，{} 个音符", group.display_name(), group.notes.len()),
+                            );
+                        }
+                    });
+                ui.separator();
+
+                ui.add_enabled_ui(self.import.bpm.is_some(), |ui| {
+                    ui.checkbox(
+                        &mut self.use_tempo,
+                        match self.import.bpm {
+                            Some(bpm) => format!("使用文件速度（BPM {bpm:.2}）"),
+                            None => String::from("文件中没有速度信息"),
+                        },
+                    );
+                });
+
+                let mut done = false;
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(self.selected.contains(&true), egui::Button::new("导入"))
+                        .clicked()
+                    {
+                        self.apply(state);
+                        done = true;
+                    }
+                    if ui.button("取消").clicked() {
+                        done = true;
+                    }
+                });
+                done
+            })
+            .inner
+    }
+}
@@ -1,3 +1,5 @@
 pub mod error_modal;
+pub mod midi_import;
+pub mod notice_modal;
 pub mod performance;
 pub mod track_editor;
@@ -0,0 +1,23 @@
+use crate::app::helpers::WidgetId;
+
+/// A modal telling the user about a recoverable failure, closed by the user.
+#[derive(Debug, Default)]
+pub struct NoticeModal<'msg> {
+    pub msg: &'msg str,
+}
+
+impl<'msg> NoticeModal<'msg> {
+    pub fn new(msg: &'msg str) -> Self {
+        Self { msg }
+    }
+    /// Returns `true` once the user dismissed the notice.
+    pub fn draw(&mut self, ctx: &egui::Context) -> bool {
+        egui::Modal::new(WidgetId::NoticeModal.into())
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new("操作失败").heading().strong());
+                ui.label(self.msg);
+                ui.button("确定").clicked()
+            })
+            .inner
+    }
+}
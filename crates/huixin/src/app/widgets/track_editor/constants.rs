@@ -1,2 +1,3 @@
 pub const TRACK_TIMELINE_HEIGHT: f32 = 100.;
 pub const TRACK_HEADER_WIDTH: f32 = 150.;
+pub const TIMELINE_LANE_HEIGHT: f32 = TRACK_TIMELINE_HEIGHT / 3.;
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use egui::containers::menu::MenuButton;
 use egui_dnd::dnd;
 
 use self::{track_header::TrackHeader, track_row::TrackRow};
@@ -27,21 +28,18 @@ impl TrackEditor {
             .frame(egui::Frame::side_top_panel(ui.style()).inner_margin(emath::vec2(6., 4.)))
             .show_inside(ui, |ui| {
                 ui.horizontal(|ui| {
-                    ui.allocate_ui_with_layout(
-                        emath::vec2(TRACK_HEADER_WIDTH - 12., 30.),
-                        egui::Layout::top_down(egui::Align::Min),
-                        |ui| {
-                            if ui
-                                .add_sized(
-                                    ui.available_size(),
-                                    egui::Button::new(egui::RichText::new("添加轨道")),
-                                )
-                                .clicked()
-                            {
-                                self.state.sheet_add_track(SheetTrackType::Pattern);
-                            };
-                        },
-                    );
+                    MenuButton::from_button(
+                        egui::Button::new("添加轨道")
+                            .min_size(emath::vec2(TRACK_HEADER_WIDTH - 12., 30.)),
+                    )
+                    .ui(ui, |ui| {
+                        for track_type in SheetTrackType::variants() {
+                            if ui.button(track_type.to_string()).clicked() {
+                                self.state.sheet_add_track(*track_type);
+                                ui.close();
+                            }
+                        }
+                    });
 
                     ui.separator();
                 });
@@ -50,8 +48,12 @@ impl TrackEditor {
         egui::ScrollArea::vertical().show(ui, |ui| {
             ui.style_mut().spacing.item_spacing = emath::vec2(0., 0.);
 
+            // rows may query the sheet (e.g. its tempo map), so don't hold the ordering lock
+            let mut ordering = self.state.sheet_tracks_ordering().clone();
+            let bar_ticks = self.state.sheet_bar_ticks(
+                self.state.sheet_length_in_beats() * self.state.sheet_ticks_per_beat(),
+            );
             let mut tracks_to_delete = Vec::new();
-            let mut ordering = self.state.sheet_tracks_ordering_mut();
             ui.horizontal(|ui| {
                 ui.vertical(|ui| {
                     dnd(ui, WidgetId::TrackEditorHeaderOrderingDnd).show_vec(
@@ -60,6 +62,7 @@ impl TrackEditor {
                             let Some(track) = self.state.sheet_get_track(track_id) else {
                                 return;
                             };
+                            self.state.history_touch_track(track_id);
                             let output =
                                 TrackHeader::new(track_id, &mut track.write(), handle).show(ui);
                             if output.delete_this_track {
@@ -75,13 +78,23 @@ impl TrackEditor {
                             let Some(track) = self.state.sheet_get_track(track_id) else {
                                 continue;
                             };
+                            self.state.history_touch_track(track_id);
                             egui::Frame::NONE.show(ui, |ui| {
-                                TrackRow::new(&mut track.write(), self.state.clone()).show(ui);
+                                TrackRow::new(
+                                    track_id,
+                                    &mut track.write(),
+                                    &bar_ticks,
+                                    self.state.clone(),
+                                )
+                                .show(ui);
                             });
                         }
                     });
                 });
             });
+            if *self.state.sheet_tracks_ordering() != ordering {
+                *self.state.sheet_tracks_ordering_mut() = ordering;
+            }
             for track_id in tracks_to_delete {
                 self.state.sheet_del_track(&track_id);
             }
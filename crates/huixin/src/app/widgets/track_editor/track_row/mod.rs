@@ -2,33 +2,61 @@ use std::sync::Arc;
 
 use lyn_util::types::WithId;
 
-use self::pattern_row::PatternTrackRow;
+use self::{
+    pattern_row::PatternTrackRow,
+    timeline_row::{TimelineLane, TimelineTrackRow, timeline_entry::TimelineEntryWidget},
+};
 use crate::{
     app::widgets::track_editor::track_row::pattern_row::track_pattern::TrackPatternWidget,
-    model::{state::CentralState, track::SheetTrack},
-    routines::metronome::TICK_PER_BEAT,
+    model::{
+        state::{CentralState, TrackId},
+        track::SheetTrack,
+    },
 };
 
 mod pattern_row;
+mod timeline_row;
 
 #[derive(Debug)]
 #[must_use]
-pub struct TrackRow<'track> {
+pub struct TrackRow<'id, 'track, 'bars> {
+    id: &'id TrackId,
     track: &'track mut SheetTrack,
+    bar_ticks: &'bars [u64],
     state: Arc<CentralState>,
 }
 
-impl<'track> TrackRow<'track> {
-    pub fn new(track: &'track mut SheetTrack, state: Arc<CentralState>) -> Self {
-        Self { track, state }
+impl<'id, 'track, 'bars> TrackRow<'id, 'track, 'bars> {
+    pub fn new(
+        id: &'id TrackId,
+        track: &'track mut SheetTrack,
+        bar_ticks: &'bars [u64],
+        state: Arc<CentralState>,
+    ) -> Self {
+        Self {
+            id,
+            track,
+            bar_ticks,
+            state,
+        }
     }
 
     pub fn show(self, ui: &mut egui::Ui) {
         let size_per_beat = *self.state.ui.track_editor_size_per_beat.read();
+        let ticks_per_beat = self.state.sheet_ticks_per_beat();
+        let tick_snap = self.state.sheet_tick_snap();
 
         match self.track {
             SheetTrack::Pattern(track) => {
-                PatternTrackRow::new(size_per_beat, track, self.state.clone()).show(ui);
+                PatternTrackRow::new(
+                    size_per_beat,
+                    ticks_per_beat,
+                    tick_snap,
+                    self.bar_ticks,
+                    track,
+                    self.state.clone(),
+                )
+                .show(ui);
 
                 let patterns = track
                     .patterns_iter()
@@ -42,15 +70,54 @@ impl<'track> TrackRow<'track> {
                     let pat = arc_pat.read();
                     TrackPatternWidget::new(
                         size_per_beat,
+                        ticks_per_beat,
                         track,
                         range,
                         WithId::new((pat_ui_id, pat_id), &pat),
-                        TICK_PER_BEAT / 4,
+                        tick_snap,
                         self.state.clone(),
                     )
                     .show(ui);
                 }
             }
+            SheetTrack::Timeline(track) => {
+                TimelineTrackRow::new(
+                    size_per_beat,
+                    ticks_per_beat,
+                    self.bar_ticks,
+                    track,
+                    tick_snap,
+                    self.state.clone(),
+                )
+                .show(ui);
+
+                let entries = track
+                    .tempos_iter()
+                    .map(|(tick, _)| (TimelineLane::Tempo, *tick))
+                    .chain(
+                        track
+                            .signatures_iter()
+                            .map(|(tick, _)| (TimelineLane::Signature, *tick)),
+                    )
+                    .chain(
+                        track
+                            .markers_iter()
+                            .map(|(tick, _)| (TimelineLane::Marker, *tick)),
+                    )
+                    .collect::<Vec<_>>();
+                for (lane, tick) in entries {
+                    TimelineEntryWidget::new(
+                        size_per_beat,
+                        ticks_per_beat,
+                        self.id,
+                        track,
+                        lane,
+                        tick,
+                        tick_snap,
+                    )
+                    .show(ui);
+                }
+            }
         }
     }
 }
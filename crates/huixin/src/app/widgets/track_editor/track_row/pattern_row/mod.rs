@@ -3,27 +3,35 @@ use std::sync::Arc;
 use crate::{
     app::widgets::track_editor::constants::TRACK_TIMELINE_HEIGHT,
     model::{pattern::SheetPatternTrait, state::CentralState, track::pattern::PatternTrack},
-    routines::metronome::TICK_PER_BEAT,
 };
 
 pub mod track_pattern;
 
 #[derive(Debug)]
 #[must_use]
-pub struct PatternTrackRow<'track> {
+pub struct PatternTrackRow<'track, 'bars> {
     size_per_beat: f32,
+    ticks_per_beat: u64,
+    tick_snap: u64,
+    bar_ticks: &'bars [u64],
     track: &'track mut PatternTrack,
     state: Arc<CentralState>,
 }
 
-impl<'track> PatternTrackRow<'track> {
+impl<'track, 'bars> PatternTrackRow<'track, 'bars> {
     pub fn new(
         size_per_beat: f32,
+        ticks_per_beat: u64,
+        tick_snap: u64,
+        bar_ticks: &'bars [u64],
         track: &'track mut PatternTrack,
         state: Arc<CentralState>,
     ) -> Self {
         Self {
             size_per_beat,
+            ticks_per_beat,
+            tick_snap,
+            bar_ticks,
             track,
             state,
         }
@@ -44,9 +52,11 @@ impl<'track> PatternTrackRow<'track> {
             && let Some(pos) = resp.interact_pointer_pos()
             && rect.contains(pos)
         {
-            let start =
-                ((pos.x - rect.left()) / self.size_per_beat * TICK_PER_BEAT as f32).floor() as u64;
-            let end = start + pat.item.read().beats() * TICK_PER_BEAT;
+            let start = ((pos.x - rect.left()) / self.size_per_beat * self.ticks_per_beat as f32)
+                .floor() as u64
+                / self.tick_snap
+                * self.tick_snap;
+            let end = start + pat.item.read().beats() * self.ticks_per_beat;
             self.track.add_pattern(start..end, pat.id);
         }
 
@@ -54,24 +64,27 @@ impl<'track> PatternTrackRow<'track> {
             let painter = ui.painter_at(rect);
 
             // vertical lines
-            for tick in 0..=length_in_beats * TICK_PER_BEAT {
-                let x = rect.left() + (tick as f32 / TICK_PER_BEAT as f32) * self.size_per_beat;
+            let line = |tick: u64, width: f32| {
+                let x =
+                    rect.left() + (tick as f32 / self.ticks_per_beat as f32) * self.size_per_beat;
                 painter.line_segment(
                     [emath::pos2(x, rect.top()), emath::pos2(x, rect.bottom())],
-                    (
-                        match (
-                            tick % (TICK_PER_BEAT * 16),
-                            tick % (TICK_PER_BEAT * 4),
-                            tick % TICK_PER_BEAT,
-                        ) {
-                            (0, _, _) => 0.8,
-                            (_, 0, _) => 0.4,
-                            (_, _, 0) => 0.2,
-                            _ => 0.1,
-                        },
-                        ui.style().noninteractive().fg_stroke.color,
-                    ),
+                    (width, ui.style().noninteractive().fg_stroke.color),
                 );
+            };
+            for tick in (0..=length_in_beats * self.ticks_per_beat).step_by(self.tick_snap as usize)
+            {
+                line(
+                    tick,
+                    if tick.is_multiple_of(self.ticks_per_beat) {
+                        0.2
+                    } else {
+                        0.1
+                    },
+                );
+            }
+            for tick in self.bar_ticks {
+                line(*tick, 0.6);
             }
 
             // border
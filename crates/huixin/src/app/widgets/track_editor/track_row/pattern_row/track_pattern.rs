@@ -7,7 +7,6 @@ use crate::{
         state::{CentralState, PatternId},
         track::pattern::PatternTrack,
     },
-    routines::metronome::TICK_PER_BEAT,
 };
 use lyn_util::{
     egui::{LynId, text_color},
@@ -20,6 +19,7 @@ pub const RESIZE_HANDLE_WIDTH: f32 = 8.;
 #[must_use]
 pub struct TrackPatternWidget<'track, 'pat> {
     size_per_beat: f32,
+    ticks_per_beat: u64,
     track: &'track mut PatternTrack,
     range: Range<u64>,
     pattern: WithId<(LynId, PatternId), &'pat SheetPattern>,
@@ -43,6 +43,7 @@ struct TrackPatternDragState {
 impl<'track, 'pat> TrackPatternWidget<'track, 'pat> {
     pub fn new(
         size_per_beat: f32,
+        ticks_per_beat: u64,
         track: &'track mut PatternTrack,
         range: Range<u64>,
         pattern: WithId<(LynId, PatternId), &'pat SheetPattern>,
@@ -51,6 +52,7 @@ impl<'track, 'pat> TrackPatternWidget<'track, 'pat> {
     ) -> Self {
         Self {
             size_per_beat,
+            ticks_per_beat,
             track,
             range,
             pattern,
@@ -75,13 +77,13 @@ impl<'track, 'pat> TrackPatternWidget<'track, 'pat> {
     /// Convert ticks to pixels based on the current `size_per_beat`.
     #[inline]
     fn ticks_to_pixels(&self, ticks: u64) -> f32 {
-        ticks as f32 / TICK_PER_BEAT as f32 * self.size_per_beat
+        ticks as f32 / self.ticks_per_beat as f32 * self.size_per_beat
     }
 
     /// Convert pixels to ticks based on the current `size_per_beat`.
     #[inline]
     fn pixels_to_ticks(&self, pixels: f32) -> i64 {
-        (pixels / self.size_per_beat * TICK_PER_BEAT as f32).round() as i64
+        (pixels / self.size_per_beat * self.ticks_per_beat as f32).round() as i64
     }
 
     /// Snap ticks to the nearest `tick_snap`.
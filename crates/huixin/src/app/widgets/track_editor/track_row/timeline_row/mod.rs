@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use crate::{
+    app::widgets::track_editor::constants::{TIMELINE_LANE_HEIGHT, TRACK_TIMELINE_HEIGHT},
+    model::{
+        state::CentralState,
+        track::timeline::{DEFAULT_MARKER_NAME, TimelineTrack},
+    },
+};
+
+pub mod timeline_entry;
+
+// LYN: Timeline Lanes
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimelineLane {
+    Tempo,
+    Signature,
+    Marker,
+}
+
+impl TimelineLane {
+    pub fn variants() -> &'static [TimelineLane] {
+        &[
+            TimelineLane::Tempo,
+            TimelineLane::Signature,
+            TimelineLane::Marker,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TimelineLane::Tempo => "速度",
+            TimelineLane::Signature => "拍号",
+            TimelineLane::Marker => "标记",
+        }
+    }
+
+    /// Vertical offset of the lane within the track row.
+    #[inline]
+    pub fn offset(&self) -> f32 {
+        let index = match self {
+            TimelineLane::Tempo => 0,
+            TimelineLane::Signature => 1,
+            TimelineLane::Marker => 2,
+        };
+        index as f32 * TIMELINE_LANE_HEIGHT
+    }
+
+    fn at_offset(offset: f32) -> Self {
+        match (offset / TIMELINE_LANE_HEIGHT) as usize {
+            0 => TimelineLane::Tempo,
+            1 => TimelineLane::Signature,
+            _ => TimelineLane::Marker,
+        }
+    }
+}
+
+// LYN: Timeline Track Row
+
+#[derive(Debug)]
+#[must_use]
+pub struct TimelineTrackRow<'track, 'bars> {
+    size_per_beat: f32,
+    ticks_per_beat: u64,
+    bar_ticks: &'bars [u64],
+    track: &'track mut TimelineTrack,
+    tick_snap: u64,
+    state: Arc<CentralState>,
+}
+
+impl<'track, 'bars> TimelineTrackRow<'track, 'bars> {
+    pub fn new(
+        size_per_beat: f32,
+        ticks_per_beat: u64,
+        bar_ticks: &'bars [u64],
+        track: &'track mut TimelineTrack,
+        tick_snap: u64,
+        state: Arc<CentralState>,
+    ) -> Self {
+        Self {
+            size_per_beat,
+            ticks_per_beat,
+            bar_ticks,
+            track,
+            tick_snap,
+            state,
+        }
+    }
+
+    fn calc_tick(&self, x: f32) -> u64 {
+        let tick = ((x / self.size_per_beat) * self.ticks_per_beat as f32).floor() as u64;
+        tick / self.tick_snap * self.tick_snap
+    }
+
+    /// Adds a new entry to the lane, inheriting the value in effect at that tick.
+    fn add_entry(&mut self, lane: TimelineLane, tick: u64) {
+        match lane {
+            TimelineLane::Tempo => {
+                let bpm = self
+                    .track
+                    .bpm_at(tick)
+                    .unwrap_or_else(|| self.state.sheet_bpm());
+                self.track.set_tempo(tick, bpm);
+            }
+            TimelineLane::Signature => {
+                let signature = self.track.signature_at(tick);
+                self.track.set_signature(tick, signature);
+            }
+            TimelineLane::Marker => {
+                self.track
+                    .set_marker(tick, String::from(DEFAULT_MARKER_NAME));
+            }
+        }
+    }
+}
+
+impl<'track, 'bars> TimelineTrackRow<'track, 'bars> {
+    pub fn show(mut self, ui: &mut egui::Ui) {
+        let length_in_beats = self.state.sheet_length_in_beats();
+        let width = length_in_beats as f32 * self.size_per_beat;
+        let desired_size = emath::vec2(width, TRACK_TIMELINE_HEIGHT);
+        let (rect, resp) = ui.allocate_exact_size(desired_size, egui::Sense::click());
+
+        if resp.clicked()
+            && let Some(pos) = resp.interact_pointer_pos()
+            && rect.contains(pos)
+        {
+            let lane = TimelineLane::at_offset(pos.y - rect.top());
+            let tick = self.calc_tick(pos.x - rect.left());
+            self.add_entry(lane, tick);
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter_at(rect);
+            let visuals = ui.style().noninteractive();
+
+            // lanes
+            for lane in TimelineLane::variants() {
+                let top = rect.top() + lane.offset();
+                if lane.offset() > 0. {
+                    painter.line_segment(
+                        [
+                            emath::pos2(rect.left(), top),
+                            emath::pos2(rect.right(), top),
+                        ],
+                        (0.2, visuals.fg_stroke.color),
+                    );
+                }
+                painter.text(
+                    emath::pos2(rect.left() + 4., top + TIMELINE_LANE_HEIGHT / 2.),
+                    egui::Align2::LEFT_CENTER,
+                    lane.label(),
+                    egui::FontId::default(),
+                    visuals.fg_stroke.color.linear_multiply(0.3),
+                );
+            }
+
+            // vertical lines
+            let line = |tick: u64, width: f32| {
+                let x =
+                    rect.left() + (tick as f32 / self.ticks_per_beat as f32) * self.size_per_beat;
+                painter.line_segment(
+                    [emath::pos2(x, rect.top()), emath::pos2(x, rect.bottom())],
+                    (width, visuals.fg_stroke.color),
+                );
+            };
+            for tick in
+                (0..=length_in_beats * self.ticks_per_beat).step_by(self.ticks_per_beat as usize)
+            {
+                line(tick, 0.1);
+            }
+            for tick in self.bar_ticks {
+                line(*tick, 0.6);
+            }
+
+            // markers
+            for (tick, _) in self.track.markers_iter() {
+                let x =
+                    rect.left() + (*tick as f32 / self.ticks_per_beat as f32) * self.size_per_beat;
+                painter.line_segment(
+                    [emath::pos2(x, rect.top()), emath::pos2(x, rect.bottom())],
+                    (1.0, self.track.color),
+                );
+            }
+
+            // border
+            painter.rect_stroke(
+                rect,
+                0.0,
+                (0.4, visuals.fg_stroke.color),
+                egui::StrokeKind::Inside,
+            );
+        }
+    }
+}
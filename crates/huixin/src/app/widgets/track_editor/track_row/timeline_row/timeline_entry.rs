@@ -0,0 +1,257 @@
+use lyn_util::egui::text_color;
+
+use crate::{
+    app::widgets::track_editor::constants::TIMELINE_LANE_HEIGHT,
+    model::{
+        state::TrackId,
+        track::timeline::{TimeSignature, TimelineTrack},
+    },
+};
+
+use super::TimelineLane;
+
+#[derive(Debug)]
+#[must_use]
+pub struct TimelineEntryWidget<'id, 'track> {
+    size_per_beat: f32,
+    ticks_per_beat: u64,
+    track_id: &'id TrackId,
+    track: &'track mut TimelineTrack,
+    lane: TimelineLane,
+    tick: u64,
+    tick_snap: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct TimelineEntryDragState {
+    pub new_tick: u64,
+}
+
+impl<'id, 'track> TimelineEntryWidget<'id, 'track> {
+    pub fn new(
+        size_per_beat: f32,
+        ticks_per_beat: u64,
+        track_id: &'id TrackId,
+        track: &'track mut TimelineTrack,
+        lane: TimelineLane,
+        tick: u64,
+        tick_snap: u64,
+    ) -> Self {
+        Self {
+            size_per_beat,
+            ticks_per_beat,
+            track_id,
+            track,
+            lane,
+            tick,
+            tick_snap,
+        }
+    }
+
+    /// Convert ticks to pixels based on the current `size_per_beat`.
+    #[inline]
+    fn ticks_to_pixels(&self, ticks: u64) -> f32 {
+        ticks as f32 / self.ticks_per_beat as f32 * self.size_per_beat
+    }
+
+    /// Convert pixels to ticks based on the current `size_per_beat`.
+    #[inline]
+    fn pixels_to_ticks(&self, pixels: f32) -> i64 {
+        (pixels / self.size_per_beat * self.ticks_per_beat as f32).round() as i64
+    }
+
+    /// Snap ticks to the nearest `tick_snap`.
+    #[inline]
+    fn snap_ticks(&self, ticks: i64) -> u64 {
+        let snap = self.tick_snap as i64;
+        ((ticks + snap / 2) / snap * snap).max(0) as u64
+    }
+
+    fn text(&self) -> Option<String> {
+        match self.lane {
+            TimelineLane::Tempo => self
+                .track
+                .tempos_iter()
+                .find(|(tick, _)| **tick == self.tick)
+                .map(|(_, bpm)| format!("󰟚 {bpm}")),
+            TimelineLane::Signature => self
+                .track
+                .signatures_iter()
+                .find(|(tick, _)| **tick == self.tick)
+                .map(|(_, sig)| sig.to_string()),
+            TimelineLane::Marker => self
+                .track
+                .markers_iter()
+                .find(|(tick, _)| **tick == self.tick)
+                .map(|(_, name)| name.clone()),
+        }
+    }
+
+    /// Moves the entry to another tick, does nothing if the tick is already taken.
+    fn move_to(&mut self, new_tick: u64) {
+        let track = &mut *self.track;
+        match self.lane {
+            TimelineLane::Tempo => {
+                if track.tempos_iter().all(|(tick, _)| *tick != new_tick)
+                    && let Some(bpm) = track.del_tempo(self.tick)
+                {
+                    track.set_tempo(new_tick, bpm);
+                }
+            }
+            TimelineLane::Signature => {
+                if track.signatures_iter().all(|(tick, _)| *tick != new_tick)
+                    && let Some(sig) = track.del_signature(self.tick)
+                {
+                    track.set_signature(new_tick, sig);
+                }
+            }
+            TimelineLane::Marker => {
+                if track.markers_iter().all(|(tick, _)| *tick != new_tick)
+                    && let Some(name) = track.del_marker(self.tick)
+                {
+                    track.set_marker(new_tick, name);
+                }
+            }
+        }
+    }
+
+    fn delete(&mut self) {
+        match self.lane {
+            TimelineLane::Tempo => {
+                self.track.del_tempo(self.tick);
+            }
+            TimelineLane::Signature => {
+                self.track.del_signature(self.tick);
+            }
+            TimelineLane::Marker => {
+                self.track.del_marker(self.tick);
+            }
+        }
+    }
+
+    fn edit_menu(&mut self, ui: &mut egui::Ui) {
+        let tick = self.tick;
+        match self.lane {
+            TimelineLane::Tempo => {
+                let mut bpm = self.track.bpm_at(tick).unwrap_or_default();
+                if ui
+                    .add(egui::DragValue::new(&mut bpm).range(1..=640).prefix("BPM "))
+                    .changed()
+                {
+                    self.track.set_tempo(tick, bpm);
+                }
+            }
+            TimelineLane::Signature => {
+                let mut sig = self.track.signature_at(tick);
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut sig.numerator).range(1..=32));
+                    ui.label("/");
+                    egui::ComboBox::from_id_salt((self.track_id, tick, "denominator"))
+                        .selected_text(sig.denominator.to_string())
+                        .width(40.)
+                        .show_ui(ui, |ui| {
+                            for denominator in TimeSignature::DENOMINATORS {
+                                ui.selectable_value(
+                                    &mut sig.denominator,
+                                    denominator,
+                                    denominator.to_string(),
+                                );
+                            }
+                        });
+                });
+                if sig != self.track.signature_at(tick) {
+                    self.track.set_signature(tick, sig);
+                }
+            }
+            TimelineLane::Marker => {
+                let mut name = self.text().unwrap_or_default();
+                if ui.text_edit_singleline(&mut name).changed() {
+                    self.track.set_marker(tick, name);
+                }
+            }
+        }
+        if ui.button("删除").clicked() {
+            self.delete();
+            ui.close();
+        }
+    }
+}
+
+// LYN: Widget Impl
+
+impl<'id, 'track> TimelineEntryWidget<'id, 'track> {
+    pub fn show(mut self, ui: &mut egui::Ui) {
+        let Some(text) = self.text() else {
+            return;
+        };
+        let anchor = ui.min_rect().left_top();
+        let color = self.track.color;
+        let galley = ui
+            .painter()
+            .layout_no_wrap(text, egui::FontId::default(), text_color(color));
+
+        let id = egui::Id::new((self.track_id, self.lane, self.tick));
+        let drag_state: Option<TimelineEntryDragState> = ui.data(|d| d.get_temp(id));
+        let draw_tick = drag_state.map_or(self.tick, |s| s.new_tick);
+        let min = emath::pos2(
+            anchor.x + self.ticks_to_pixels(draw_tick),
+            anchor.y + self.lane.offset() + 2.,
+        );
+        let rect = egui::Rect::from_min_size(
+            min,
+            emath::vec2(galley.size().x + 8., TIMELINE_LANE_HEIGHT - 4.),
+        );
+
+        let resp = ui.interact(rect, id, egui::Sense::click_and_drag());
+        resp.context_menu(|ui| self.edit_menu(ui));
+
+        if resp.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+
+        if resp.dragged() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+
+            let total_drag = ui.input(|i| {
+                i.pointer
+                    .press_origin()
+                    .map(|origin| i.pointer.interact_pos().unwrap_or(origin) - origin)
+                    .unwrap_or(egui::Vec2::ZERO)
+            });
+            let new_tick = self.snap_ticks(self.tick as i64 + self.pixels_to_ticks(total_drag.x));
+            ui.data_mut(|d| d.insert_temp(id, TimelineEntryDragState { new_tick }));
+        }
+
+        if resp.drag_stopped() {
+            ui.data_mut(|d| d.remove::<TimelineEntryDragState>(id));
+            if let Some(drag_state) = drag_state
+                && drag_state.new_tick != self.tick
+            {
+                self.move_to(drag_state.new_tick);
+            }
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter();
+
+            let stroke_color = if resp.hovered() || resp.dragged() {
+                ecolor::Color32::WHITE
+            } else {
+                color.lerp_to_gamma(ecolor::Color32::BLACK, 0.5)
+            };
+
+            painter.rect(
+                rect,
+                2.0,
+                color,
+                egui::Stroke::new(1.0, stroke_color),
+                egui::StrokeKind::Middle,
+            );
+            painter.galley(
+                rect.left_center() + emath::vec2(4., -galley.size().y / 2.),
+                galley,
+                text_color(color),
+            );
+        }
+    }
+}
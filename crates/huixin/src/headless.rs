@@ -0,0 +1,298 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use log::{error, info, warn};
+
+use crate::{
+    app::PlayerContext,
+    model::{
+        render::{self, RenderFormat},
+        state::CentralState,
+    },
+    routines::{self, remote_control::RemoteControlConfig},
+};
+
+pub const PLAY_SUBCOMMAND: &str = "play";
+pub const RENDER_SUBCOMMAND: &str = "render";
+
+const PLAYBACK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Time given to the routines to send out the last instructions before exiting.
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+const PLAY_USAGE: &str = "\
+用法: huixin play <工程文件> [选项]
+
+选项:
+    --loop                  循环播放（默认只播放一遍）
+    --bpm <BPM>             以固定速度播放，忽略工程及时间轴上的速度
+    --target <名称>=<地址>  覆盖指定通信目标的地址，可多次使用
+    --lookahead <毫秒>      提前发送指令并附带执行时间（默认即时发送）
+    --remote <地址>         在指定地址上开启远程控制（WebSocket）
+    -h, --help              显示此帮助";
+
+const RENDER_USAGE: &str = "\
+用法: huixin render <工程文件> [选项]
+
+不连接任何目标，将整个工程一次发出的指令写为带时间戳的记录。
+
+选项:
+    -o, --output <文件>     写入到文件（默认输出到标准输出）
+    --format <jsonl|csv>    输出格式（默认按输出文件扩展名，否则为 jsonl）
+    --bpm <BPM>             以固定速度计算时间，忽略工程及时间轴上的速度
+    -h, --help              显示此帮助";
+
+// LYN: Headless Player
+
+#[derive(Debug, Default)]
+struct PlayOptions {
+    project: PathBuf,
+    looping: bool,
+    bpm: Option<f64>,
+    targets: Vec<(String, String)>,
+    lookahead: Duration,
+    remote: Option<String>,
+}
+
+/// Plays a saved sheet without any UI, until it ends or SIGINT is received.
+/// Returns the exit code of the process.
+pub fn play(args: impl Iterator<Item = String>) -> i32 {
+    let options = match parse_play_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{PLAY_USAGE}");
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{PLAY_USAGE}");
+            return 2;
+        }
+    };
+
+    let state = Arc::new(CentralState::init());
+    if !load_project(&state, &options.project) {
+        return 1;
+    }
+
+    for (name, addr) in options.targets.iter() {
+        let mut matched = false;
+        for entry in state.sheet_comm_targets_iter() {
+            let mut target = entry.write();
+            if target.name == *name {
+                target.addr = addr.clone();
+                matched = true;
+            }
+        }
+        if !matched {
+            error!("No communication target named {name:?} in the project");
+            return 1;
+        }
+    }
+
+    // connect up front, the instructor would drop the first instructions while connecting
+    for entry in state.sheet_comm_targets_iter() {
+        let (name, addr, format) = {
+            let target = entry.read();
+            (target.name.clone(), target.addr.clone(), target.format)
+        };
+        if let Err(e) = state.comm_connect_stream_blocking(entry.key().clone(), &addr, format) {
+            warn!("Failed to connect to target {name:?} at {addr}, will keep retrying: {e}");
+        }
+    }
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    if let Err(e) = ctrlc::set_handler({
+        let interrupted = interrupted.clone();
+        move || interrupted.store(true, Ordering::SeqCst)
+    }) {
+        warn!("Failed to install SIGINT handler: {e}");
+    }
+
+    state.player_set_context(PlayerContext::Sheet);
+    state.metro_set_looping(options.looping);
+    state.metro_override_bpm(options.bpm);
+    state.comm_set_lookahead(options.lookahead);
+    if let Some(addr) = options.remote {
+        *state.remote_config_mut() = RemoteControlConfig {
+            enabled: true,
+            addr,
+        };
+    }
+    routines::launch(state.clone());
+    state.metro_toggle_playing(Some(true));
+    info!("Playing {:?}", options.project);
+
+    let code = loop {
+        thread::sleep(PLAYBACK_POLL_INTERVAL);
+        if interrupted.load(Ordering::SeqCst) {
+            info!("Interrupted, stopping playback");
+            break 0;
+        }
+        if let Some(msg) = state.app_get_err_msg().as_ref() {
+            error!("{msg}");
+            break 1;
+        }
+        // pausing or stopping through the remote control keeps the process around
+        if state.metro_finished() {
+            info!("Reached the end of the sheet");
+            break 0;
+        }
+    };
+    state.metro_toggle_playing(Some(false));
+    let jitter = state.metro_jitter();
+    info!(
+        "Tick jitter over {} ticks: {:?} mean, {:?} max",
+        jitter.samples,
+        jitter.mean(),
+        jitter.max
+    );
+    let lagged = state.metro_lagged_ticks();
+    if lagged > 0 {
+        warn!("Lagged behind {lagged} ticks");
+    }
+    thread::sleep(SHUTDOWN_GRACE);
+    code
+}
+
+// LYN: Offline Renderer
+
+#[derive(Debug, Default)]
+struct RenderOptions {
+    project: PathBuf,
+    output: Option<PathBuf>,
+    format: Option<RenderFormat>,
+    bpm: Option<f64>,
+}
+
+/// Writes everything a saved sheet sends in one pass, without waiting or connecting to targets.
+/// Returns the exit code of the process.
+pub fn render(args: impl Iterator<Item = String>) -> i32 {
+    let options = match parse_render_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{RENDER_USAGE}");
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{RENDER_USAGE}");
+            return 2;
+        }
+    };
+
+    let state = Arc::new(CentralState::init());
+    if !load_project(&state, &options.project) {
+        return 1;
+    }
+    state.metro_override_bpm(options.bpm);
+
+    let format = options
+        .format
+        .or_else(|| options.output.as_deref().and_then(RenderFormat::from_path))
+        .unwrap_or_default();
+    let written = render::render_sheet(&state).and_then(|rendered| match &options.output {
+        Some(path) => fs::File::create(path)
+            .map_err(Into::into)
+            .and_then(|file| render::write_rendered(&rendered, format, io::BufWriter::new(file))),
+        None => render::write_rendered(&rendered, format, io::stdout().lock()),
+    });
+    match written {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("Failed to render {:?}: {}", options.project, e);
+            1
+        }
+    }
+}
+
+// LYN: Helpers
+
+fn load_project(state: &CentralState, path: &Path) -> bool {
+    let loaded = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|str| state.sheet_from_json_str(&str).map_err(|e| e.to_string()));
+    if let Err(e) = &loaded {
+        error!("Failed to load project file {path:?}: {e}");
+    }
+    loaded.is_ok()
+}
+
+fn parse_bpm(value: Option<String>) -> Result<f64, String> {
+    let value = value.ok_or("--bpm 缺少参数")?;
+    let bpm: f64 = value.parse().map_err(|_| format!("无效的 BPM：{value}"))?;
+    if !(1. ..=640.).contains(&bpm) {
+        return Err(format!("BPM 需在 1 到 640 之间：{value}"));
+    }
+    Ok(bpm)
+}
+
+/// Returns `None` if help was asked for.
+fn parse_play_args(mut args: impl Iterator<Item = String>) -> Result<Option<PlayOptions>, String> {
+    let mut options = PlayOptions::default();
+    let mut project = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--loop" => options.looping = true,
+            "--bpm" => options.bpm = Some(parse_bpm(args.next())?),
+            "--target" => {
+                let value = args.next().ok_or("--target 缺少参数")?;
+                let (name, addr) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("目标需写作 <名称>=<地址>：{value}"))?;
+                options.targets.push((name.to_string(), addr.to_string()));
+            }
+            "--lookahead" => {
+                let value = args.next().ok_or("--lookahead 缺少参数")?;
+                let millis: u64 = value
+                    .parse()
+                    .map_err(|_| format!("无效的提前时间：{value}"))?;
+                options.lookahead = Duration::from_millis(millis);
+            }
+            "--remote" => {
+                let value = args.next().ok_or("--remote 缺少参数")?;
+                options.remote = Some(value);
+            }
+            flag if flag.starts_with('-') => return Err(format!("未知选项：{flag}")),
+            _ if project.is_some() => return Err(format!("多余的参数：{arg}")),
+            _ => project = Some(PathBuf::from(arg)),
+        }
+    }
+    options.project = project.ok_or("缺少工程文件")?;
+    Ok(Some(options))
+}
+
+/// Returns `None` if help was asked for.
+fn parse_render_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<RenderOptions>, String> {
+    let mut options = RenderOptions::default();
+    let mut project = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => {
+                let value = args.next().ok_or("--output 缺少参数")?;
+                options.output = Some(PathBuf::from(value));
+            }
+            "--format" => {
+                let value = args.next().ok_or("--format 缺少参数")?;
+                let format = RenderFormat::from_name(&value)
+                    .ok_or_else(|| format!("未知的输出格式：{value}"))?;
+                options.format = Some(format);
+            }
+            "--bpm" => options.bpm = Some(parse_bpm(args.next())?),
+            flag if flag.starts_with('-') => return Err(format!("未知选项：{flag}")),
+            _ if project.is_some() => return Err(format!("多余的参数：{arg}")),
+            _ => project = Some(PathBuf::from(arg)),
+        }
+    }
+    options.project = project.ok_or("缺少工程文件")?;
+    Ok(Some(options))
+}
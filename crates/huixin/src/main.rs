@@ -1,9 +1,12 @@
+use std::process;
+
 use lyn_util::egui::EguiContextExt;
 use simplelog::TermLogger;
 
 use crate::app::MainApp;
 
 mod app;
+mod headless;
 mod model;
 mod routines;
 
@@ -12,6 +15,13 @@ const APP_ID: &str = "huixin";
 fn main() -> eframe::Result {
     init_logger().expect("Fail to start logger");
 
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some(headless::PLAY_SUBCOMMAND) => process::exit(headless::play(args)),
+        Some(headless::RENDER_SUBCOMMAND) => process::exit(headless::render(args)),
+        _ => {}
+    }
+
     let mut app = MainApp::init();
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
@@ -1,6 +1,12 @@
-use std::net::TcpStream;
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    net::{TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
 
 use lyn_util::comm::{Format, Instruction};
+use parking_lot::{Condvar, Mutex};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use ws::WebSocket;
@@ -13,10 +19,48 @@ pub struct SheetMessage {
     pub payload: Instruction,
 }
 
+/// Every message due to one target at once, sent as a single frame.
+#[derive(Debug, Clone)]
+pub struct SheetBatch {
+    pub target_id: TargetId,
+    /// The tick the messages were read at.
+    pub tick: u64,
+    pub payloads: Vec<Instruction>,
+}
+
+impl SheetBatch {
+    /// Groups messages by their target, in the order the targets first appear.
+    pub fn group(tick: u64, msgs: impl IntoIterator<Item = SheetMessage>) -> Vec<SheetBatch> {
+        let mut batches: Vec<SheetBatch> = Vec::new();
+        for msg in msgs {
+            match batches
+                .iter_mut()
+                .find(|batch| batch.target_id == msg.target_id)
+            {
+                Some(batch) => batch.payloads.push(msg.payload),
+                None => batches.push(SheetBatch {
+                    target_id: msg.target_id,
+                    tick,
+                    payloads: vec![msg.payload],
+                }),
+            }
+        }
+        batches
+    }
+
+    #[inline]
+    pub fn form_bytes(&self, format: Format) -> Option<Vec<u8>> {
+        Instruction::form_batch_bytes(self.payloads.clone(), format)
+    }
+}
+
 #[derive(Debug)]
 pub enum CommStream {
     WebSocket(Box<WebSocket<TcpStream>>),
     TcpStream(TcpStream),
+    UdpSocket(UdpSocket),
+    /// Delivered to the loopback sink in the app.
+    Loopback,
 }
 
 #[derive(Debug, Error)]
@@ -27,6 +71,30 @@ pub enum CommStreamErr {
     Std(#[from] std::io::Error),
     #[error("No communication stream connected")]
     NoCommStream,
+    #[error("Already connecting")]
+    Connecting,
+    #[error("Invalid address {0:?}")]
+    InvalidAddr(String),
+    #[error("WebSocket handshake failed: {0}")]
+    Handshake(String),
+    #[error("Heartbeat timed out")]
+    HeartbeatTimeout,
+    #[error("Loopback received a malformed frame: {0}")]
+    Loopback(#[from] json::Error),
+}
+
+/// How the connection to a target is doing, kept up by its writer.
+#[derive(Debug, Clone, Default)]
+pub struct CommHealth {
+    pub connected_since: Option<Instant>,
+    pub last_error: Option<String>,
+    /// Round trip time of the last heartbeat, only WebSocket targets answer them.
+    pub latency: Option<Duration>,
+    /// When the next connection attempt is due, while backing off.
+    pub retry_at: Option<Instant>,
+    /// Instructions sent, a batch counts each of its instructions.
+    pub msgs_sent: u64,
+    pub bytes_sent: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -34,6 +102,7 @@ pub struct CommTarget {
     pub name: String,
     pub addr: String,
     pub format: Format,
+    pub overflow: OverflowPolicy,
 }
 
 impl Default for CommTarget {
@@ -42,6 +111,7 @@ impl Default for CommTarget {
             name: "未命名".to_string(),
             addr: DEFAULT_COMM_TARGET_ADDR.to_string(),
             format: Format::default(),
+            overflow: OverflowPolicy::default(),
         }
     }
 }
@@ -56,12 +126,140 @@ impl<'de> Deserialize<'de> for CommTarget {
             name: String,
             addr: String,
             format: Format,
+            #[serde(default)]
+            overflow: OverflowPolicy,
         }
         let deser = CommTargetDeser::deserialize(deserializer)?;
         Ok(CommTarget {
             name: deser.name,
             addr: deser.addr,
             format: deser.format,
+            overflow: deser.overflow,
         })
     }
 }
+
+// LYN: Send Queue
+
+/// What to do with a batch when the send queue of its target is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverflowPolicy {
+    /// Stale batches are the least useful once a target lags behind.
+    #[default]
+    DropOldest,
+    DropNewest,
+    /// Waits for room in the queue, holding up every other target meanwhile.
+    Block,
+}
+
+impl Display for OverflowPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverflowPolicy::DropOldest => write!(f, "丢弃最早"),
+            OverflowPolicy::DropNewest => write!(f, "丢弃最新"),
+            OverflowPolicy::Block => write!(f, "等待"),
+        }
+    }
+}
+
+impl OverflowPolicy {
+    pub fn variants() -> &'static [OverflowPolicy] {
+        &[
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::Block,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SendQueueStats {
+    /// Batches waiting to be sent.
+    pub depth: usize,
+    pub sent: u64,
+    /// Batches dropped because the queue was full.
+    pub dropped: u64,
+    /// Batches lost because the target could not be reached.
+    pub failed: u64,
+}
+
+/// Bounded FIFO queue of the batches to one target, drained by its own writer.
+#[derive(Debug, Default)]
+pub struct SendQueue {
+    inner: Mutex<SendQueueInner>,
+    pushed: Condvar,
+    popped: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct SendQueueInner {
+    batches: VecDeque<SheetBatch>,
+    closed: bool,
+    stats: SendQueueStats,
+}
+
+impl SendQueue {
+    pub const CAPACITY: usize = 256;
+
+    pub fn push(&self, batch: SheetBatch, policy: OverflowPolicy) {
+        let mut inner = self.inner.lock();
+        if inner.batches.len() >= Self::CAPACITY {
+            match policy {
+                OverflowPolicy::DropOldest => {
+                    inner.batches.pop_front();
+                    inner.stats.dropped += 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    inner.stats.dropped += 1;
+                    return;
+                }
+                OverflowPolicy::Block => {
+                    while inner.batches.len() >= Self::CAPACITY && !inner.closed {
+                        self.popped.wait(&mut inner);
+                    }
+                }
+            }
+        }
+        if inner.closed {
+            return;
+        }
+        inner.batches.push_back(batch);
+        inner.stats.depth = inner.batches.len();
+        self.pushed.notify_one();
+    }
+
+    /// Waits up to `timeout` for the next batch, returns `None` if there was none.
+    pub fn pop(&self, timeout: Duration) -> Option<SheetBatch> {
+        let mut inner = self.inner.lock();
+        if inner.batches.is_empty() && !inner.closed {
+            self.pushed.wait_for(&mut inner, timeout);
+        }
+        let batch = inner.batches.pop_front()?;
+        inner.stats.depth = inner.batches.len();
+        self.popped.notify_one();
+        Some(batch)
+    }
+
+    pub fn report_sent(&self) {
+        self.inner.lock().stats.sent += 1;
+    }
+    pub fn report_failed(&self) {
+        self.inner.lock().stats.failed += 1;
+    }
+    pub fn stats(&self) -> SendQueueStats {
+        self.inner.lock().stats
+    }
+
+    /// Discards pending batches and lets the writer stop.
+    pub fn close(&self) {
+        let mut inner = self.inner.lock();
+        inner.closed = true;
+        inner.batches.clear();
+        inner.stats.depth = 0;
+        self.pushed.notify_all();
+        self.popped.notify_all();
+    }
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().closed
+    }
+}
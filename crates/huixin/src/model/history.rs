@@ -0,0 +1,281 @@
+use std::{collections::HashMap, num::NonZero};
+
+use serde::Serialize;
+
+use crate::model::{
+    comm::CommTarget,
+    pattern::SheetPattern,
+    state::{PatternId, TargetId, TrackId},
+    track::SheetTrack,
+};
+
+/// Maximum number of undo steps kept in the history.
+pub const HISTORY_LIMIT: usize = 128;
+
+// LYN: History
+
+/// Undo/redo stacks of the sheet.
+///
+/// Edits are recorded as commands on the items they touch: a mutator captures an item the first
+/// time it touches it within the open step, and the step is closed into an undo step of
+/// `before -> after` commands. A step stays open for a whole gesture (pointer held, key typed,
+/// ...), so dragging a note around only records one step.
+#[derive(Debug, Default)]
+pub struct History {
+    pending: Option<PendingStep>,
+    /// Whether an editing gesture keeps the pending step open.
+    gesture: bool,
+    /// Number of transactions currently running.
+    transactions: usize,
+    undo_stack: Vec<HistoryStep>,
+    redo_stack: Vec<HistoryStep>,
+    /// Revision of the sheet with an empty undo stack.
+    base_revision: u64,
+    next_revision: u64,
+}
+
+impl History {
+    /// Identifies the current content of the sheet, the same content after undo/redo has the
+    /// same revision.
+    #[inline]
+    pub fn revision(&self) -> u64 {
+        self.undo_stack
+            .last()
+            .map_or(self.base_revision, |step| step.revision)
+    }
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+    #[inline]
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+    /// Returns the open step, if any, for mutators to capture the items they are about to touch.
+    #[inline]
+    pub fn pending(&self) -> Option<&PendingStep> {
+        self.pending.as_ref()
+    }
+    #[inline]
+    pub fn pending_mut(&mut self) -> Option<&mut PendingStep> {
+        self.pending.as_mut()
+    }
+
+    pub fn begin_gesture(&mut self) {
+        self.gesture = true;
+        self.pending.get_or_insert_default();
+    }
+    /// Ends the gesture, returning the step to close unless a transaction is still running.
+    pub fn end_gesture(&mut self) -> Option<PendingStep> {
+        self.gesture = false;
+        self.take_closable()
+    }
+    pub fn begin_transaction(&mut self) {
+        self.transactions += 1;
+        self.pending.get_or_insert_default();
+    }
+    /// Ends a transaction, returning the step to close unless it is nested in another
+    /// transaction or a gesture.
+    pub fn end_transaction(&mut self) -> Option<PendingStep> {
+        self.transactions = self.transactions.saturating_sub(1);
+        self.take_closable()
+    }
+    fn take_closable(&mut self) -> Option<PendingStep> {
+        if self.gesture || self.transactions > 0 {
+            return None;
+        }
+        self.pending.take()
+    }
+
+    /// Records the commands of a closed step as an undo step, if anything changed.
+    pub fn push(&mut self, commands: Vec<HistoryCommand>) {
+        if commands.is_empty() {
+            return;
+        }
+        self.next_revision += 1;
+        self.undo_stack.push(HistoryStep {
+            revision: self.next_revision,
+            commands,
+        });
+        if self.undo_stack.len() > HISTORY_LIMIT {
+            // undoing everything now ends right after the dropped step
+            self.base_revision = self.undo_stack.remove(0).revision;
+        }
+        self.redo_stack.clear();
+    }
+
+    pub fn pop_undo(&mut self) -> Option<HistoryStep> {
+        let step = self.undo_stack.pop()?;
+        self.redo_stack.push(step.clone());
+        Some(step)
+    }
+    pub fn pop_redo(&mut self) -> Option<HistoryStep> {
+        let step = self.redo_stack.pop()?;
+        self.undo_stack.push(step.clone());
+        Some(step)
+    }
+
+    pub fn clear(&mut self) {
+        self.pending = None;
+        self.gesture = false;
+        self.transactions = 0;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.next_revision += 1;
+        self.base_revision = self.next_revision;
+    }
+}
+
+// LYN: Pending Step
+
+/// Items touched within the open step, as they were before the first touch.
+///
+/// `None` means the item did not exist yet.
+#[derive(Debug, Default)]
+pub struct PendingStep {
+    props: Option<SheetProps>,
+    tracks: HashMap<TrackId, Option<SheetTrack>>,
+    patterns: HashMap<PatternId, Option<SheetPattern>>,
+    targets: HashMap<TargetId, Option<CommTarget>>,
+}
+
+impl PendingStep {
+    #[inline]
+    pub fn props_touched(&self) -> bool {
+        self.props.is_some()
+    }
+    #[inline]
+    pub fn track_touched(&self, id: &TrackId) -> bool {
+        self.tracks.contains_key(id)
+    }
+    #[inline]
+    pub fn pattern_touched(&self, id: &PatternId) -> bool {
+        self.patterns.contains_key(id)
+    }
+    #[inline]
+    pub fn target_touched(&self, id: &TargetId) -> bool {
+        self.targets.contains_key(id)
+    }
+
+    pub fn touch_props(&mut self, props: SheetProps) {
+        self.props.get_or_insert(props);
+    }
+    pub fn touch_track(&mut self, id: TrackId, track: Option<SheetTrack>) {
+        self.tracks.entry(id).or_insert(track);
+    }
+    pub fn touch_pattern(&mut self, id: PatternId, pattern: Option<SheetPattern>) {
+        self.patterns.entry(id).or_insert(pattern);
+    }
+    pub fn touch_target(&mut self, id: TargetId, target: Option<CommTarget>) {
+        self.targets.entry(id).or_insert(target);
+    }
+
+    /// Turns the touched items into commands by comparing them with their current values,
+    /// items left unchanged are dropped.
+    pub fn into_commands(
+        self,
+        props: impl FnOnce() -> SheetProps,
+        track: impl Fn(&TrackId) -> Option<SheetTrack>,
+        pattern: impl Fn(&PatternId) -> Option<SheetPattern>,
+        target: impl Fn(&TargetId) -> Option<CommTarget>,
+    ) -> Vec<HistoryCommand> {
+        let mut commands = Vec::new();
+        if let Some(before) = self.props {
+            let after = props();
+            if before != after {
+                commands.push(HistoryCommand::Props { before, after });
+            }
+        }
+        commands.extend(
+            changed_items(self.tracks, track).map(|(id, before, after)| HistoryCommand::Track {
+                id,
+                before,
+                after,
+            }),
+        );
+        commands.extend(
+            changed_items(self.patterns, pattern)
+                .map(|(id, before, after)| HistoryCommand::Pattern { id, before, after }),
+        );
+        commands.extend(
+            changed_items(self.targets, target).map(|(id, before, after)| HistoryCommand::Target {
+                id,
+                before,
+                after,
+            }),
+        );
+        commands
+    }
+}
+
+// LYN: History Step
+
+/// A group of commands undone or redone together.
+#[derive(Debug, Clone)]
+pub struct HistoryStep {
+    /// Revision of the sheet right after this step.
+    pub revision: u64,
+    pub commands: Vec<HistoryCommand>,
+}
+
+/// A single reversible edit, `None` means the item did not exist (or no longer exists).
+///
+/// Items are boxed, they differ a lot in size.
+#[derive(Debug, Clone)]
+pub enum HistoryCommand {
+    Props {
+        before: SheetProps,
+        after: SheetProps,
+    },
+    Track {
+        id: TrackId,
+        before: Option<Box<SheetTrack>>,
+        after: Option<Box<SheetTrack>>,
+    },
+    Pattern {
+        id: PatternId,
+        before: Option<Box<SheetPattern>>,
+        after: Option<Box<SheetPattern>>,
+    },
+    Target {
+        id: TargetId,
+        before: Option<Box<CommTarget>>,
+        after: Option<Box<CommTarget>>,
+    },
+}
+
+// LYN: Sheet Props
+
+/// Plain values of the sheet outside of its tracks, patterns and targets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetProps {
+    pub bpm: f64,
+    pub ticks_per_beat: u64,
+    pub length_in_beats: NonZero<u64>,
+    pub tracks_ordering: Vec<TrackId>,
+    pub patterns_ordering: Vec<PatternId>,
+    pub targets_ordering: Vec<TargetId>,
+}
+
+// LYN: Helpers
+
+/// Items are compared by their serialized form, which leaves out UI only ids.
+fn changed_items<K, V>(
+    touched: HashMap<K, Option<V>>,
+    current: impl Fn(&K) -> Option<V>,
+) -> impl Iterator<Item = (K, Option<Box<V>>, Option<Box<V>>)>
+where
+    V: Serialize,
+{
+    touched.into_iter().filter_map(move |(id, before)| {
+        let after = current(&id);
+        let unchanged = match (&before, &after) {
+            (Some(before), Some(after)) => {
+                json::to_value(before).ok() == json::to_value(after).ok()
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        (!unchanged).then(|| (id, before.map(Box::new), after.map(Box::new)))
+    })
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::VecDeque,
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lyn_util::comm::Instruction;
+use serde::Deserialize;
+
+use crate::routines::beeper::Beep;
+
+/// Seconds received notes stay around after they end, for the visualiser.
+pub const LOOPBACK_HISTORY_SECS: f64 = 10.;
+const MAX_NOTES: usize = 512;
+const MAX_EVENTS: usize = 64;
+
+// LYN: Loopback Sink
+
+/// Receives whatever is sent to loopback targets, so sheets can be tried without any other
+/// software listening.
+///
+/// Instructions carrying a `midicode` are taken as notes, ending after their `length` in ticks
+/// or at a `strength` of zero, anything else is kept as a plain event.
+///
+/// Notes are read as MIDI patterns send them, `strength` ranging up to `u16::MAX`. Instructions
+/// rewritten by a mapping are shown as they arrive, so a rescaled `strength` or renamed fields
+/// do not show as intended.
+#[derive(Debug, Default)]
+pub struct LoopbackSink {
+    notes: VecDeque<LoopbackNote>,
+    events: VecDeque<LoopbackEvent>,
+    received: u64,
+    beep: bool,
+    beeper: Option<mpsc::Sender<Beep>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopbackNote {
+    pub target: String,
+    pub tag: String,
+    pub midicode: u8,
+    /// From `0` to `1`.
+    pub strength: f32,
+    /// When the note starts sounding, in seconds since the UNIX epoch.
+    pub start: f64,
+    /// `None` while sounding until told otherwise.
+    pub end: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopbackEvent {
+    /// Seconds since the UNIX epoch.
+    pub time: f64,
+    pub target: String,
+    pub tag: String,
+    pub data: String,
+}
+
+impl LoopbackSink {
+    /// Decodes a frame as `WsBasedJson` sends it, a single instruction or a batch of them.
+    ///
+    /// `tick_secs` is how long a tick lasts at the moment, note lengths are given in ticks.
+    pub fn receive(
+        &mut self,
+        target: &str,
+        frame: &[u8],
+        tick_secs: f64,
+    ) -> Result<(), json::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Frame {
+            Batch(Vec<Instruction>),
+            Single(Instruction),
+        }
+        let instructions = match json::from_slice(frame)? {
+            Frame::Batch(batch) => batch,
+            Frame::Single(instruction) => vec![instruction],
+        };
+
+        let now = unix_now();
+        for instruction in instructions {
+            self.received += 1;
+            let time = instruction.timestamp.unwrap_or(now);
+            let midicode = instruction
+                .get("midicode")
+                .and_then(|value| value.as_u64())
+                .and_then(|midicode| u8::try_from(midicode).ok());
+            match midicode {
+                Some(midicode) => {
+                    let strength = instruction
+                        .get("strength")
+                        .and_then(|value| value.as_f64())
+                        .unwrap_or(0.);
+                    let end = instruction
+                        .get("length")
+                        .and_then(|value| value.as_f64())
+                        .map(|length| time + length * tick_secs);
+                    self.note(target, &instruction.tag, midicode, strength, time, end);
+                }
+                None => {
+                    if self.events.len() >= MAX_EVENTS {
+                        self.events.pop_front();
+                    }
+                    self.events.push_back(LoopbackEvent {
+                        time,
+                        target: target.to_string(),
+                        tag: instruction.tag.clone(),
+                        data: json::to_string(&instruction.data).unwrap_or_default(),
+                    });
+                }
+            }
+        }
+        self.forget_before(now - LOOPBACK_HISTORY_SECS);
+        Ok(())
+    }
+
+    fn note(
+        &mut self,
+        target: &str,
+        tag: &str,
+        midicode: u8,
+        strength: f64,
+        time: f64,
+        end: Option<f64>,
+    ) {
+        // a note starting again ends the one still sounding
+        if let Some(note) = self.notes.iter_mut().rev().find(|note| {
+            note.end.is_none_or(|end| end > time)
+                && note.midicode == midicode
+                && note.tag == tag
+                && note.target == target
+        }) {
+            note.end = Some(time);
+        }
+        if strength <= 0. {
+            return;
+        }
+
+        let strength = (strength / u16::MAX as f64).clamp(0., 1.) as f32;
+        self.notes.push_back(LoopbackNote {
+            target: target.to_string(),
+            tag: tag.to_string(),
+            midicode,
+            strength,
+            start: time,
+            end,
+        });
+        if self.beep
+            && let Some(beeper) = self.beeper.as_ref()
+        {
+            // the beeper only goes away along with the app
+            let _ = beeper.send(Beep {
+                midicode,
+                strength,
+                timestamp: time,
+            });
+        }
+    }
+
+    fn forget_before(&mut self, time: f64) {
+        while self.notes.len() > MAX_NOTES
+            || self
+                .notes
+                .front()
+                .is_some_and(|note| note.end.is_some_and(|end| end < time))
+        {
+            self.notes.pop_front();
+        }
+    }
+
+    #[inline]
+    pub fn notes(&self) -> &VecDeque<LoopbackNote> {
+        &self.notes
+    }
+    #[inline]
+    pub fn events(&self) -> &VecDeque<LoopbackEvent> {
+        &self.events
+    }
+    /// Number of instructions received since last cleared.
+    #[inline]
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+    pub fn clear(&mut self) {
+        self.notes.clear();
+        self.events.clear();
+        self.received = 0;
+    }
+
+    #[inline]
+    pub fn beep(&self) -> bool {
+        self.beep
+    }
+    pub fn set_beep(&mut self, beep: bool) {
+        self.beep = beep;
+    }
+    pub fn connect_beeper(&mut self, beeper: mpsc::Sender<Beep>) {
+        self.beeper = Some(beeper);
+    }
+}
+
+// LYN: Helpers
+
+/// Returns the seconds since the UNIX epoch.
+pub fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0., |since| since.as_secs_f64())
+}
@@ -1,11 +1,25 @@
 pub mod comm;
+pub mod history;
+pub mod loopback;
 pub mod pattern;
 pub mod persistence;
+pub mod render;
+pub mod smf;
 pub mod state;
 pub mod track;
+pub mod traffic;
 
 pub const DEFAULT_COMM_TARGET_ADDR: &str = "127.0.0.1:3000";
 pub const DEFAULT_SELECTABLE_COLOR: ecolor::Color32 = ecolor::Color32::from_rgb(100, 149, 237);
 pub const DEFAULT_TRACK_NAME: &str = "未命名轨道";
 pub const DEFAULT_PATTERN_NAME: &str = "未命名片段";
 pub const DEFAULT_ICON: &str = "󰝚 ";
+pub const DEFAULT_TICK_PER_BEAT: u64 = 96;
+/// Tick resolution of sheets saved before it became configurable.
+pub const LEGACY_TICK_PER_BEAT: u64 = 4;
+
+/// Converts a tick from one resolution to another, rounding to the nearest tick.
+#[inline]
+pub fn rescale_tick(tick: u64, from: u64, to: u64) -> u64 {
+    ((tick as u128 * to as u128 + from as u128 / 2) / from as u128) as u64
+}
@@ -1,6 +1,336 @@
-// LYN: Pattern - Curve
+use std::fmt::Display;
 
-// #[derive(Debug)]
-// pub struct CurvePattern {
-//     vals: Vec<Timed<f64>>,
-// }
+use lyn_util::{
+    comm::{DataMap, Instruction},
+    egui::LynId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::model::{
+    DEFAULT_ICON, DEFAULT_PATTERN_NAME, DEFAULT_SELECTABLE_COLOR, comm::SheetMessage, rescale_tick,
+    state::TargetId,
+};
+
+use super::SheetPatternTrait;
+
+// LYN: Curve Pattern
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CurvePattern {
+    // pattern
+    pub name: String,
+    pub icon: String,
+    pub color: ecolor::Color32,
+    /// total ticks = beats * ticks per beat of the sheet
+    pub beats: u64,
+
+    // pattern internal
+    /// lower bound of the value range, only used for editing
+    pub min: f64,
+    /// upper bound of the value range, only used for editing
+    pub max: f64,
+    /// breakpoints, always sorted by `tick`
+    points: Vec<CurvePoint>,
+
+    // communication
+    pub tag: String,
+    pub target_id: Option<TargetId>,
+}
+
+impl CurvePattern {
+    pub const VALUE_KEY: &str = "value";
+
+    pub fn new() -> Self {
+        Self {
+            name: String::from(DEFAULT_PATTERN_NAME),
+            icon: String::from(DEFAULT_ICON),
+            color: DEFAULT_SELECTABLE_COLOR,
+            beats: 1,
+            min: 0.,
+            max: 1.,
+            points: Vec::new(),
+            tag: String::new(),
+            target_id: None,
+        }
+    }
+
+    pub fn points_iter_owned(&self) -> impl Iterator<Item = CurvePoint> {
+        self.points.iter().copied()
+    }
+    pub fn get_point(&self, id: LynId) -> Option<CurvePoint> {
+        self.points.iter().find(|p| p.id() == id).copied()
+    }
+    pub fn add_point(&mut self, point: CurvePoint) {
+        let idx = self.points.partition_point(|p| p.tick <= point.tick);
+        self.points.insert(idx, point);
+    }
+    pub fn del_point(&mut self, id: LynId) {
+        self.points.retain(|p| p.id() != id);
+    }
+    pub fn edit_point(&mut self, id: LynId, f: impl FnOnce(&mut CurvePoint)) {
+        let Some(idx) = self.points.iter().position(|p| p.id() == id) else {
+            return;
+        };
+        let mut point = self.points.remove(idx);
+        f(&mut point);
+        self.add_point(point);
+    }
+
+    /// Samples the curve at the given (possibly fractional) tick.
+    ///
+    /// Before the first breakpoint the curve holds the first value, after the last one it
+    /// holds the last value. Returns `None` if the curve has no breakpoint at all.
+    pub fn value_at(&self, tick: f64) -> Option<f64> {
+        let first = self.points.first()?;
+        let idx = self.points.partition_point(|p| p.tick as f64 <= tick);
+        if idx == 0 {
+            return Some(first.value);
+        }
+        let from = self.points[idx - 1];
+        let Some(to) = self.points.get(idx) else {
+            return Some(from.value);
+        };
+        let span = (to.tick - from.tick) as f64;
+        let t = if span > 0. {
+            (tick - from.tick as f64) / span
+        } else {
+            1.
+        };
+        Some(from.interp.interpolate(from.value, to.value, t))
+    }
+
+    #[inline]
+    pub fn min_beats(&self, ticks_per_beat: u64) -> u64 {
+        self.points
+            .last()
+            .map(|p| p.tick.div_ceil(ticks_per_beat).max(1))
+            .unwrap_or(1)
+    }
+    pub fn rescale_ticks(&mut self, from: u64, to: u64) {
+        for point in self.points.iter_mut() {
+            point.tick = rescale_tick(point.tick, from, to);
+        }
+    }
+}
+
+impl SheetPatternTrait for CurvePattern {
+    #[inline]
+    fn name_ref(&self) -> &String {
+        &self.name
+    }
+
+    #[inline]
+    fn icon_ref(&self) -> &String {
+        &self.icon
+    }
+
+    #[inline]
+    fn color(&self) -> ecolor::Color32 {
+        self.color
+    }
+
+    #[inline]
+    fn usable(&self) -> bool {
+        self.target_id.is_some() && !self.tag.is_empty()
+    }
+
+    #[inline]
+    fn beats(&self) -> u64 {
+        self.beats
+    }
+
+    #[inline]
+    fn msg_at(&self, tick: u64) -> Vec<SheetMessage> {
+        let Some(target_id) = self.target_id.as_ref() else {
+            return Vec::new();
+        };
+        let Some(value) = self.value_at(tick as f64) else {
+            return Vec::new();
+        };
+        // only the first tick and actual changes are sent, flat segments stay silent
+        if tick > 0 && self.value_at((tick - 1) as f64) == Some(value) {
+            return Vec::new();
+        }
+
+        let mut data = DataMap::new();
+        data.insert(Self::VALUE_KEY.to_string(), value.into());
+        vec![SheetMessage {
+            target_id: target_id.clone(),
+            payload: Instruction {
+                tag: self.tag.clone(),
+                data,
+                timestamp: None,
+                format: None,
+            },
+        }]
+    }
+}
+
+impl<'de> Deserialize<'de> for CurvePattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct CurvePatternDeser {
+            name: String,
+            icon: String,
+            color: ecolor::Color32,
+            beats: u64,
+            min: f64,
+            max: f64,
+            points: Vec<CurvePoint>,
+            tag: String,
+            target_id: Option<TargetId>,
+        }
+        let mut deser = CurvePatternDeser::deserialize(deserializer)?;
+        deser.points.sort_by_key(|p| p.tick);
+        Ok(Self {
+            name: deser.name,
+            icon: deser.icon,
+            color: deser.color,
+            beats: deser.beats,
+            min: deser.min,
+            max: deser.max,
+            points: deser.points,
+            tag: deser.tag,
+            target_id: deser.target_id,
+        })
+    }
+}
+
+// LYN: Curve Point
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CurvePoint {
+    #[serde(skip_serializing)]
+    id: LynId,
+    pub tick: u64,
+    pub value: f64,
+    /// how the curve goes from this point to the next one
+    pub interp: CurveInterp,
+}
+
+impl CurvePoint {
+    pub fn new(tick: u64, value: f64, interp: CurveInterp) -> Self {
+        Self {
+            id: LynId::obtain(),
+            tick,
+            value,
+            interp,
+        }
+    }
+    #[inline]
+    pub fn id(&self) -> LynId {
+        self.id
+    }
+}
+
+impl<'de> Deserialize<'de> for CurvePoint {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct CurvePointDeser {
+            tick: u64,
+            value: f64,
+            interp: CurveInterp,
+        }
+        let deser = CurvePointDeser::deserialize(deserializer)?;
+        Ok(Self {
+            id: LynId::obtain(),
+            tick: deser.tick,
+            value: deser.value,
+            interp: deser.interp,
+        })
+    }
+}
+
+// LYN: Curve Interpolation
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum CurveInterp {
+    /// holds the value until the next point
+    Step,
+    #[default]
+    Linear,
+    /// geometric interpolation, falls back to linear if the values cross or touch zero
+    Exponential,
+    /// cubic bezier easing with control points `(x1, y1)` and `(x2, y2)`, like CSS
+    Bezier { x1: f64, y1: f64, x2: f64, y2: f64 },
+}
+
+impl Display for CurveInterp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CurveInterp::Step => write!(f, "阶梯"),
+            CurveInterp::Linear => write!(f, "线性"),
+            CurveInterp::Exponential => write!(f, "指数"),
+            CurveInterp::Bezier { .. } => write!(f, "贝塞尔"),
+        }
+    }
+}
+
+impl CurveInterp {
+    pub const EASE_IN_OUT: CurveInterp = CurveInterp::Bezier {
+        x1: 0.42,
+        y1: 0.,
+        x2: 0.58,
+        y2: 1.,
+    };
+
+    pub fn variants() -> &'static [CurveInterp] {
+        &[
+            CurveInterp::Step,
+            CurveInterp::Linear,
+            CurveInterp::Exponential,
+            CurveInterp::EASE_IN_OUT,
+        ]
+    }
+
+    /// Interpolates between `from` and `to`, `t` is the progress within `0.0..=1.0`.
+    pub fn interpolate(&self, from: f64, to: f64, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        let progress = match *self {
+            CurveInterp::Step => return if t < 1. { from } else { to },
+            CurveInterp::Linear => t,
+            CurveInterp::Exponential => {
+                if from * to > 0. {
+                    return from * (to / from).powf(t);
+                }
+                t
+            }
+            CurveInterp::Bezier { x1, y1, x2, y2 } => cubic_bezier_ease(x1, y1, x2, y2, t),
+        };
+        from + (to - from) * progress
+    }
+}
+
+// LYN: Helpers
+
+/// Evaluates a CSS-style cubic bezier easing function at `x`.
+fn cubic_bezier_ease(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    let bezier = |p1: f64, p2: f64, s: f64| {
+        let inv = 1. - s;
+        3. * inv * inv * s * p1 + 3. * inv * s * s * p2 + s * s * s
+    };
+
+    // the x component is monotonic for x1, x2 in 0..=1, so bisection always converges
+    let (x1, x2) = (x1.clamp(0., 1.), x2.clamp(0., 1.));
+    let (mut lo, mut hi) = (0., 1.);
+    let mut s = x;
+    for _ in 0..32 {
+        let guess = bezier(x1, x2, s);
+        if (guess - x).abs() < 1e-6 {
+            break;
+        }
+        if guess < x {
+            lo = s;
+        } else {
+            hi = s;
+        }
+        s = (lo + hi) / 2.;
+    }
+    bezier(y1, y2, s)
+}
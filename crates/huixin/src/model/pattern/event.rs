@@ -1,4 +1,190 @@
-// LYN: Pattern - Event
+use std::collections::HashMap;
 
-// #[derive(Debug)]
-// pub struct EventPattern {}
+use lyn_util::{
+    comm::{DataMap, Instruction},
+    egui::LynId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::model::{
+    DEFAULT_ICON, DEFAULT_PATTERN_NAME, DEFAULT_SELECTABLE_COLOR, comm::SheetMessage, rescale_tick,
+    state::TargetId,
+};
+
+use super::SheetPatternTrait;
+
+// LYN: Event Pattern
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventPattern {
+    // pattern
+    pub name: String,
+    pub icon: String,
+    pub color: ecolor::Color32,
+    /// total ticks = beats * ticks per beat of the sheet
+    pub beats: u64,
+
+    // pattern internal
+    events: HashMap<u64, Vec<PatternEvent>>,
+
+    // communication
+    pub target_id: Option<TargetId>,
+}
+
+impl EventPattern {
+    pub fn new() -> Self {
+        Self {
+            name: String::from(DEFAULT_PATTERN_NAME),
+            icon: String::from(DEFAULT_ICON),
+            color: DEFAULT_SELECTABLE_COLOR,
+            beats: 1,
+            events: HashMap::new(),
+            target_id: None,
+        }
+    }
+
+    pub fn events_iter(&self) -> impl Iterator<Item = &PatternEvent> {
+        self.events.values().flatten()
+    }
+    pub fn get_event(&self, id: LynId) -> Option<&PatternEvent> {
+        self.events_iter().find(|e| e.id() == id)
+    }
+    pub fn add_event(&mut self, event: PatternEvent) {
+        self.events.entry(event.tick).or_default().push(event);
+    }
+    pub fn del_event(&mut self, id: LynId) {
+        self.events.retain(|_, vec| {
+            vec.retain(|e| e.id() != id);
+            !vec.is_empty()
+        });
+    }
+    pub fn edit_event(&mut self, id: LynId, f: impl FnOnce(&mut PatternEvent)) {
+        let Some((&tick, vec)) = self
+            .events
+            .iter_mut()
+            .find(|(_, vec)| vec.iter().any(|e| e.id() == id))
+        else {
+            return;
+        };
+        let idx = vec.iter().position(|e| e.id() == id).unwrap();
+        f(&mut vec[idx]);
+        if vec[idx].tick != tick {
+            let moved_event = vec.remove(idx);
+            if vec.is_empty() {
+                self.events.remove(&tick);
+            }
+            self.add_event(moved_event);
+        }
+    }
+    #[inline]
+    pub fn min_beats(&self, ticks_per_beat: u64) -> u64 {
+        self.events
+            .keys()
+            .max()
+            .map(|max_tick| (max_tick + 1).div_ceil(ticks_per_beat))
+            .unwrap_or(1)
+    }
+    pub fn rescale_ticks(&mut self, from: u64, to: u64) {
+        let events = std::mem::take(&mut self.events);
+        for mut event in events.into_values().flatten() {
+            event.tick = rescale_tick(event.tick, from, to);
+            self.add_event(event);
+        }
+    }
+}
+
+impl SheetPatternTrait for EventPattern {
+    #[inline]
+    fn name_ref(&self) -> &String {
+        &self.name
+    }
+
+    #[inline]
+    fn icon_ref(&self) -> &String {
+        &self.icon
+    }
+
+    #[inline]
+    fn color(&self) -> ecolor::Color32 {
+        self.color
+    }
+
+    #[inline]
+    fn usable(&self) -> bool {
+        self.target_id.is_some() && self.events_iter().all(|e| !e.tag.is_empty())
+    }
+
+    #[inline]
+    fn beats(&self) -> u64 {
+        self.beats
+    }
+
+    #[inline]
+    fn msg_at(&self, tick: u64) -> Vec<SheetMessage> {
+        let Some(target_id) = self.target_id.as_ref() else {
+            return Vec::new();
+        };
+        self.events.get(&tick).map_or_else(Vec::new, |events| {
+            events
+                .iter()
+                .filter(|event| !event.tag.is_empty())
+                .map(|event| SheetMessage {
+                    target_id: target_id.clone(),
+                    payload: Instruction {
+                        tag: event.tag.clone(),
+                        data: event.data.clone(),
+                        timestamp: None,
+                        format: None,
+                    },
+                })
+                .collect()
+        })
+    }
+}
+
+// LYN: Pattern Event
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PatternEvent {
+    #[serde(skip_serializing)]
+    id: LynId,
+    pub tick: u64,
+    pub tag: String,
+    pub data: DataMap,
+}
+
+impl PatternEvent {
+    pub fn new(tick: u64, tag: String, data: DataMap) -> Self {
+        Self {
+            id: LynId::obtain(),
+            tick,
+            tag,
+            data,
+        }
+    }
+    #[inline]
+    pub fn id(&self) -> LynId {
+        self.id
+    }
+}
+
+impl<'de> Deserialize<'de> for PatternEvent {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct PatternEventDeser {
+            tick: u64,
+            tag: String,
+            data: DataMap,
+        }
+        let deser = PatternEventDeser::deserialize(deserializer)?;
+        Ok(Self {
+            id: LynId::obtain(),
+            tick: deser.tick,
+            tag: deser.tag,
+            data: deser.data,
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use lyn_util::comm::DataMap;
+use serde::{Deserialize, Serialize};
+
+// LYN: Instruction Mapping
+
+/// Rewrites the instructions of a pattern before they are sent, so receivers do not have to
+/// follow the schema of the pattern.
+///
+/// Routes are matched against the original data, then fields are renamed and scaled, and
+/// constant fields are added last. The strength of a note-off is never scaled, so it stays
+/// the `0` receivers tell note-offs apart by.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstructionMapping {
+    pub routes: Vec<NoteRoute>,
+    pub fields: Vec<FieldRule>,
+    pub constants: Vec<ConstantField>,
+}
+
+impl InstructionMapping {
+    const OFF_KEY: &str = "strength";
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty() && self.fields.is_empty() && self.constants.is_empty()
+    }
+
+    pub fn apply(&self, tag: &str, mut data: DataMap) -> (String, DataMap) {
+        if self.is_empty() {
+            return (tag.to_string(), data);
+        }
+        let tag = data
+            .get(NoteRoute::MATCH_KEY)
+            .and_then(|value| value.as_u64())
+            .and_then(|midicode| self.routes.iter().find(|route| route.matches(midicode)))
+            .map_or(tag, |route| route.tag.as_str())
+            .to_string();
+
+        let is_off = data.get(Self::OFF_KEY).and_then(|value| value.as_u64()) == Some(0);
+        for rule in self.fields.iter() {
+            let Some(value) = data.remove(&rule.key) else {
+                continue;
+            };
+            let key = if rule.rename.is_empty() {
+                rule.key.clone()
+            } else {
+                rule.rename.clone()
+            };
+            let value = if is_off && rule.key == Self::OFF_KEY {
+                value
+            } else {
+                rule.transform(value)
+            };
+            data.insert(key, value);
+        }
+
+        for constant in self.constants.iter().filter(|c| !c.key.is_empty()) {
+            data.insert(constant.key.clone(), constant.json_value());
+        }
+        (tag, data)
+    }
+}
+
+/// Sends notes in a range of midicodes to their own tag, e.g. each piece of a drum kit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteRoute {
+    pub low: u8,
+    pub high: u8,
+    pub tag: String,
+}
+
+impl NoteRoute {
+    const MATCH_KEY: &str = "midicode";
+
+    #[inline]
+    fn matches(&self, midicode: u64) -> bool {
+        (self.low as u64..=self.high as u64).contains(&midicode)
+    }
+}
+
+impl Default for NoteRoute {
+    fn default() -> Self {
+        Self {
+            low: 0,
+            high: 127,
+            tag: String::new(),
+        }
+    }
+}
+
+/// Renames a field and maps its numeric value onto `value * scale + offset`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldRule {
+    pub key: String,
+    /// Empty to keep the key.
+    pub rename: String,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl FieldRule {
+    fn transform(&self, value: json::Value) -> json::Value {
+        if self.scale == 1. && self.offset == 0. {
+            return value;
+        }
+        let Some(number) = value.as_f64() else {
+            return value;
+        };
+        let mapped = number * self.scale + self.offset;
+        // whole numbers stay integers as long as the rule keeps them whole
+        if value.is_i64() && self.scale.fract() == 0. && self.offset.fract() == 0. {
+            (mapped as i64).into()
+        } else {
+            mapped.into()
+        }
+    }
+}
+
+impl Default for FieldRule {
+    fn default() -> Self {
+        Self {
+            key: String::new(),
+            rename: String::new(),
+            scale: 1.,
+            offset: 0.,
+        }
+    }
+}
+
+/// A field added to every instruction, its value is read as JSON, or else taken as a string.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConstantField {
+    pub key: String,
+    pub value: String,
+}
+
+impl ConstantField {
+    fn json_value(&self) -> json::Value {
+        json::from_str(&self.value).unwrap_or_else(|_| json::Value::String(self.value.clone()))
+    }
+}
@@ -1,4 +1,7 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
 
 use either::Either;
 use lyn_util::{
@@ -7,15 +10,12 @@ use lyn_util::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{
-    model::{
-        DEFAULT_ICON, DEFAULT_SELECTABLE_COLOR, DEFAULT_TRACK_NAME, comm::SheetMessage,
-        state::TargetId,
-    },
-    routines::metronome::TICK_PER_BEAT,
+use crate::model::{
+    DEFAULT_ICON, DEFAULT_SELECTABLE_COLOR, DEFAULT_TRACK_NAME, comm::SheetMessage, rescale_tick,
+    state::TargetId,
 };
 
-use super::SheetPatternTrait;
+use super::{SheetPatternTrait, mapping::InstructionMapping};
 
 // LYN: Midi Pattern
 
@@ -25,7 +25,7 @@ pub struct MidiPattern {
     pub name: String,
     pub icon: String,
     pub color: ecolor::Color32,
-    /// total ticks = beats / TICK_PER_BEAT
+    /// total ticks = beats * ticks per beat of the sheet
     pub beats: u64,
 
     // pattern internal
@@ -36,6 +36,8 @@ pub struct MidiPattern {
     // communication
     pub tag: String,
     pub target_id: Option<TargetId>,
+    pub note_mode: MidiNoteMode,
+    pub mapping: InstructionMapping,
 }
 
 impl MidiPattern {
@@ -49,6 +51,8 @@ impl MidiPattern {
             notes: HashMap::new(),
             tag: String::new(),
             target_id: None,
+            note_mode: MidiNoteMode::default(),
+            mapping: InstructionMapping::default(),
         }
     }
 
@@ -174,13 +178,38 @@ impl MidiPattern {
         }
     }
     #[inline]
-    pub fn min_beats(&self) -> u64 {
+    pub fn min_beats(&self, ticks_per_beat: u64) -> u64 {
         self.end_tick_map
             .iter()
             .next_back()
-            .map(|(max_end_tick, _)| max_end_tick.div_ceil(TICK_PER_BEAT))
+            .map(|(max_end_tick, _)| max_end_tick.div_ceil(ticks_per_beat))
             .unwrap_or(1)
     }
+    pub fn rescale_ticks(&mut self, from: u64, to: u64) {
+        let notes = self.notes_iter_owned().collect::<Vec<_>>();
+        self.notes.clear();
+        self.end_tick_map.clear();
+        for mut note in notes {
+            let end = rescale_tick(note.end_tick(), from, to);
+            note.start = rescale_tick(note.start, from, to);
+            note.length = end.saturating_sub(note.start).max(1);
+            self.add_note(note);
+        }
+    }
+
+    #[inline]
+    fn form_msg(&self, target_id: &TargetId, data: DataMap) -> SheetMessage {
+        let (tag, data) = self.mapping.apply(&self.tag, data);
+        SheetMessage {
+            target_id: target_id.clone(),
+            payload: Instruction {
+                tag,
+                data,
+                timestamp: None,
+                format: None,
+            },
+        }
+    }
 }
 
 impl SheetPatternTrait for MidiPattern {
@@ -209,24 +238,40 @@ impl SheetPatternTrait for MidiPattern {
         self.beats
     }
 
+    /// Note-offs are sent before note-ons, so a note re-triggered right as it ends is not cut.
     #[inline]
     fn msg_at(&self, tick: u64) -> Vec<SheetMessage> {
         let Some(target_id) = self.target_id.as_ref() else {
             return Vec::new();
         };
-        self.notes.get(&tick).map_or_else(Vec::new, |notes| {
-            notes
-                .iter()
-                .map(|note| SheetMessage {
-                    target_id: target_id.clone(),
-                    payload: Instruction {
-                        tag: self.tag.clone(),
-                        data: note.form_data(),
-                        format: None,
-                    },
-                })
-                .collect()
-        })
+        let mut msgs = Vec::new();
+        if self.note_mode.sends_off() && self.end_tick_map.contains_key(&tick) {
+            msgs.extend(
+                self.notes_iter_owned()
+                    .filter(|note| note.end_tick() == tick)
+                    .map(|note| self.form_msg(target_id, note.form_off_data())),
+            );
+        }
+        if let Some(notes) = self.notes.get(&tick) {
+            msgs.extend(notes.iter().map(|note| {
+                self.form_msg(target_id, note.form_on_data(self.note_mode.sends_length()))
+            }));
+        }
+        msgs
+    }
+
+    #[inline]
+    fn release_at(&self, tick: u64) -> Vec<SheetMessage> {
+        let Some(target_id) = self.target_id.as_ref() else {
+            return Vec::new();
+        };
+        if !self.note_mode.sends_off() {
+            return Vec::new();
+        }
+        self.notes_iter_owned()
+            .filter(|note| note.start < tick && note.end_tick() >= tick)
+            .map(|note| self.form_msg(target_id, note.form_off_data()))
+            .collect()
     }
 }
 
@@ -244,6 +289,10 @@ impl<'de> Deserialize<'de> for MidiPattern {
             notes: HashMap<u64, Vec<MidiNote>>,
             tag: String,
             target_id: Option<TargetId>,
+            #[serde(default)]
+            note_mode: MidiNoteMode,
+            #[serde(default)]
+            mapping: InstructionMapping,
         }
         let deser = MidiPatternDeser::deserialize(deserializer)?;
         let mut end_tick_map = BTreeMap::new();
@@ -264,10 +313,54 @@ impl<'de> Deserialize<'de> for MidiPattern {
             notes: deser.notes,
             tag: deser.tag,
             target_id: deser.target_id,
+            note_mode: deser.note_mode,
+            mapping: deser.mapping,
         })
     }
 }
 
+// LYN: Midi Note Mode
+
+/// Decides which instructions a MIDI pattern sends for each of its notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MidiNoteMode {
+    /// A single note-on carrying the note length, the receiver decides when to stop.
+    #[default]
+    OnWithLength,
+    /// A note-on when the note starts and a note-off (strength `0`) when it ends.
+    OnOff,
+    /// A note-on carrying the note length, followed by a note-off when it ends.
+    Both,
+}
+
+impl Display for MidiNoteMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MidiNoteMode::OnWithLength => write!(f, "带时长的音符"),
+            MidiNoteMode::OnOff => write!(f, "按下/松开"),
+            MidiNoteMode::Both => write!(f, "带时长的音符 + 松开"),
+        }
+    }
+}
+
+impl MidiNoteMode {
+    pub fn variants() -> &'static [MidiNoteMode] {
+        &[
+            MidiNoteMode::OnWithLength,
+            MidiNoteMode::OnOff,
+            MidiNoteMode::Both,
+        ]
+    }
+    #[inline]
+    pub fn sends_length(&self) -> bool {
+        matches!(self, MidiNoteMode::OnWithLength | MidiNoteMode::Both)
+    }
+    #[inline]
+    pub fn sends_off(&self) -> bool {
+        matches!(self, MidiNoteMode::OnOff | MidiNoteMode::Both)
+    }
+}
+
 // LYN: Midi Note
 
 #[derive(Debug, Clone, Copy, Serialize)]
@@ -299,11 +392,21 @@ impl MidiNote {
         self.start + self.length
     }
     #[inline]
-    pub fn form_data(&self) -> DataMap {
+    pub fn form_on_data(&self, with_length: bool) -> DataMap {
         let mut map = DataMap::new();
         map.insert("midicode".to_string(), self.midicode.into());
         map.insert("strength".to_string(), self.strength.into());
-        map.insert("length".to_string(), self.length.into());
+        if with_length {
+            map.insert("length".to_string(), self.length.into());
+        }
+        map
+    }
+    /// A note-off shares the tag of the note-on, it is told apart by a strength of `0`.
+    #[inline]
+    pub fn form_off_data(&self) -> DataMap {
+        let mut map = DataMap::new();
+        map.insert("midicode".to_string(), self.midicode.into());
+        map.insert("strength".to_string(), 0.into());
         map
     }
 }
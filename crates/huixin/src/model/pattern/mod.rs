@@ -1,25 +1,59 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
 use crate::model::comm::SheetMessage;
 
-use self::midi::MidiPattern;
+use self::{curve::CurvePattern, event::EventPattern, midi::MidiPattern};
 
 pub mod curve;
 pub mod event;
+pub mod mapping;
 pub mod midi;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SheetPatternType {
     Midi,
-    // Curve,
-    // Event,
+    Curve,
+    Event,
+}
+
+impl Display for SheetPatternType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SheetPatternType::Midi => write!(f, "MIDI 片段"),
+            SheetPatternType::Curve => write!(f, "曲线片段"),
+            SheetPatternType::Event => write!(f, "事件片段"),
+        }
+    }
+}
+
+impl SheetPatternType {
+    pub fn variants() -> &'static [SheetPatternType] {
+        &[
+            SheetPatternType::Midi,
+            SheetPatternType::Curve,
+            SheetPatternType::Event,
+        ]
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SheetPattern {
     Midi(MidiPattern),
-    // Curve(CurvePattern),
-    // Event(EventPattern),
+    Curve(CurvePattern),
+    Event(EventPattern),
+}
+
+impl SheetPattern {
+    /// Converts all ticks of the pattern from one resolution to another.
+    pub fn rescale_ticks(&mut self, from: u64, to: u64) {
+        match self {
+            Self::Midi(pat) => pat.rescale_ticks(from, to),
+            Self::Curve(pat) => pat.rescale_ticks(from, to),
+            Self::Event(pat) => pat.rescale_ticks(from, to),
+        }
+    }
 }
 
 pub trait SheetPatternTrait {
@@ -30,6 +64,10 @@ pub trait SheetPatternTrait {
 
     fn beats(&self) -> u64;
     fn msg_at(&self, tick: u64) -> Vec<SheetMessage>;
+    /// Messages releasing whatever is still sounding when playback stops at the given tick.
+    fn release_at(&self, _tick: u64) -> Vec<SheetMessage> {
+        Vec::new()
+    }
 }
 
 impl SheetPatternTrait for SheetPattern {
@@ -37,6 +75,8 @@ impl SheetPatternTrait for SheetPattern {
     fn name_ref(&self) -> &String {
         match self {
             Self::Midi(pat) => pat.name_ref(),
+            Self::Curve(pat) => pat.name_ref(),
+            Self::Event(pat) => pat.name_ref(),
         }
     }
 
@@ -44,6 +84,8 @@ impl SheetPatternTrait for SheetPattern {
     fn icon_ref(&self) -> &String {
         match self {
             Self::Midi(pat) => pat.icon_ref(),
+            Self::Curve(pat) => pat.icon_ref(),
+            Self::Event(pat) => pat.icon_ref(),
         }
     }
 
@@ -51,6 +93,8 @@ impl SheetPatternTrait for SheetPattern {
     fn color(&self) -> ecolor::Color32 {
         match self {
             Self::Midi(pat) => pat.color(),
+            Self::Curve(pat) => pat.color(),
+            Self::Event(pat) => pat.color(),
         }
     }
 
@@ -58,6 +102,8 @@ impl SheetPatternTrait for SheetPattern {
     fn usable(&self) -> bool {
         match self {
             Self::Midi(pat) => pat.usable(),
+            Self::Curve(pat) => pat.usable(),
+            Self::Event(pat) => pat.usable(),
         }
     }
 
@@ -65,12 +111,24 @@ impl SheetPatternTrait for SheetPattern {
     fn beats(&self) -> u64 {
         match self {
             Self::Midi(pat) => pat.beats(),
+            Self::Curve(pat) => pat.beats(),
+            Self::Event(pat) => pat.beats(),
         }
     }
     #[inline]
     fn msg_at(&self, tick: u64) -> Vec<SheetMessage> {
         match self {
             Self::Midi(pat) => pat.msg_at(tick),
+            Self::Curve(pat) => pat.msg_at(tick),
+            Self::Event(pat) => pat.msg_at(tick),
+        }
+    }
+    #[inline]
+    fn release_at(&self, tick: u64) -> Vec<SheetMessage> {
+        match self {
+            Self::Midi(pat) => pat.release_at(tick),
+            Self::Curve(pat) => pat.release_at(tick),
+            Self::Event(pat) => pat.release_at(tick),
         }
     }
 }
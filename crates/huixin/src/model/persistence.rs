@@ -1,6 +1,12 @@
-use std::path::PathBuf;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::model::{DEFAULT_TICK_PER_BEAT, LEGACY_TICK_PER_BEAT, rescale_tick};
 
 // LYN: Working Directory
 
@@ -9,8 +15,65 @@ pub struct WorkingDirectory(pub PathBuf);
 
 impl WorkingDirectory {
     const STATE_DIR_NAME: &str = "states";
+    const AUTOSAVE_DIR_NAME: &str = "autosave";
+    const UNTITLED_AUTOSAVE_NAME: &str = "untitled";
+    pub const PROJECT_FILE_EXTENSION: &str = "json";
+
+    /// Directory the project files of this working directory live in by default.
+    pub fn state_dir(&self) -> PathBuf {
+        self.0.join(Self::STATE_DIR_NAME)
+    }
     pub fn state_path(&self, id: &str) -> PathBuf {
-        self.0.join(Self::STATE_DIR_NAME).join(format!("{id}.json"))
+        self.state_dir()
+            .join(format!("{id}.{}", Self::PROJECT_FILE_EXTENSION))
+    }
+    /// Where the autosave snapshots of the given project go, `None` for a never saved sheet.
+    ///
+    /// Keyed by the stem plus a hash of the full path, so same-named projects don't collide.
+    pub fn autosave_path(&self, project: Option<&Path>) -> PathBuf {
+        let name = project.map_or_else(
+            || String::from(Self::UNTITLED_AUTOSAVE_NAME),
+            |path| {
+                let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                format!("{stem}-{:016x}", path_hash(&path))
+            },
+        );
+        self.state_dir()
+            .join(Self::AUTOSAVE_DIR_NAME)
+            .join(format!("{name}.{}", Self::PROJECT_FILE_EXTENSION))
+    }
+}
+
+/// FNV-1a over the path bytes, stable across builds unlike the std hasher.
+fn path_hash(path: &Path) -> u64 {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Writes the file through a temporary sibling, so a crash midway never leaves it half written.
+pub fn write_file_atomic(path: &Path, content: impl AsRef<[u8]>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Whether the snapshot was written after the file it backs up, or the file does not exist.
+pub fn snapshot_is_newer(snapshot: &Path, file: Option<&Path>) -> bool {
+    let Ok(snapshot_time) = fs::metadata(snapshot).and_then(|meta| meta.modified()) else {
+        return false;
+    };
+    match file.map(|file| fs::metadata(file).and_then(|meta| meta.modified())) {
+        Some(Ok(file_time)) => snapshot_time > file_time,
+        _ => true,
     }
 }
 
@@ -20,6 +83,182 @@ impl From<PathBuf> for WorkingDirectory {
     }
 }
 
+// LYN: Sheet File Format
+
+/// Version of the sheet files written by this build.
+///
+/// - `0`: bare sheet, ticks fixed at 4 per beat
+/// - `1`: sheet with `ticks_per_beat`, wrapped in a `{ version, sheet }` header
+pub const SHEET_FORMAT_VERSION: u64 = MIGRATIONS.len() as u64;
+
+type Migration = fn(&mut json::Value) -> Result<(), SheetFileErr>;
+
+/// Migrations upgrading the bare sheet value, the one at index `i` goes from version `i` to `i + 1`.
+const MIGRATIONS: [Migration; 1] = [migrate_v0_to_v1];
+
+#[derive(Debug, Error)]
+pub enum SheetFileErr {
+    #[error("文件不是有效的 JSON：{0}")]
+    Parse(json::Error),
+    #[error("文件版本 v{version} 高于当前支持的 v{supported}，请升级程序")]
+    TooNew { version: u64, supported: u64 },
+    #[error("无法识别的文件结构：{0}")]
+    Malformed(&'static str),
+    #[error("文件内容已损坏：{0}")]
+    Corrupt(json::Error),
+}
+
+/// Wraps a serialized sheet into a file of the current format version.
+pub fn wrap_sheet_value(sheet: json::Value) -> json::Value {
+    json::json!({
+        "version": SHEET_FORMAT_VERSION,
+        "sheet": sheet,
+    })
+}
+
+/// Parses a sheet file of any known version, upgrading it step by step to the current one.
+///
+/// Returns the bare sheet value.
+pub fn unwrap_sheet_file(s: &str) -> Result<json::Value, SheetFileErr> {
+    let value: json::Value = json::from_str(s).map_err(SheetFileErr::Parse)?;
+    let json::Value::Object(mut file) = value else {
+        return Err(SheetFileErr::Malformed("顶层不是对象"));
+    };
+
+    let (version, mut sheet) = match file.remove("version") {
+        Some(version) => {
+            let version = version
+                .as_u64()
+                .ok_or(SheetFileErr::Malformed("版本号不是整数"))?;
+            let sheet = file
+                .remove("sheet")
+                .ok_or(SheetFileErr::Malformed("缺少 sheet 字段"))?;
+            (version, sheet)
+        }
+        // files written before the header existed, the ones with a tick resolution are up to date
+        None => {
+            let version = if file.contains_key("ticks_per_beat") {
+                1
+            } else {
+                0
+            };
+            (version, json::Value::Object(file))
+        }
+    };
+    if version > SHEET_FORMAT_VERSION {
+        return Err(SheetFileErr::TooNew {
+            version,
+            supported: SHEET_FORMAT_VERSION,
+        });
+    }
+    if !sheet.is_object() {
+        return Err(SheetFileErr::Malformed("sheet 字段不是对象"));
+    }
+
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(&mut sheet)?;
+    }
+    Ok(sheet)
+}
+
+/// Brings the fixed tick resolution up to the default one, rescaling every tick in the sheet.
+///
+/// Version 0 only knew MIDI patterns and pattern tracks, so only their ticks are rewritten.
+fn migrate_v0_to_v1(sheet: &mut json::Value) -> Result<(), SheetFileErr> {
+    let sheet = sheet
+        .as_object_mut()
+        .ok_or(SheetFileErr::Malformed("sheet 字段不是对象"))?;
+    let (from, to) = (LEGACY_TICK_PER_BEAT, DEFAULT_TICK_PER_BEAT);
+    for pattern in items_mut(sheet, "patterns")? {
+        if let Some(midi) = pattern.get_mut("Midi") {
+            rescale_midi_notes(midi, from, to)?;
+        }
+    }
+    for track in items_mut(sheet, "tracks")? {
+        if let Some(track) = track.get_mut("Pattern") {
+            rescale_track_ranges(track, from, to)?;
+        }
+    }
+    sheet.insert("ticks_per_beat".to_string(), to.into());
+    Ok(())
+}
+
+/// Values of an `id -> item` map of the sheet, nothing if the map is missing.
+fn items_mut<'a>(
+    sheet: &'a mut json::Map<String, json::Value>,
+    key: &str,
+) -> Result<impl Iterator<Item = &'a mut json::Value>, SheetFileErr> {
+    let items = match sheet.get_mut(key) {
+        Some(items) => Some(
+            items
+                .as_object_mut()
+                .ok_or(SheetFileErr::Malformed("条目列表不是对象"))?,
+        ),
+        None => None,
+    };
+    Ok(items.into_iter().flat_map(|items| items.values_mut()))
+}
+
+/// Rescales the notes of a MIDI pattern, re-keying them by their new start tick.
+fn rescale_midi_notes(pattern: &mut json::Value, from: u64, to: u64) -> Result<(), SheetFileErr> {
+    let Some(notes) = pattern.get_mut("notes") else {
+        return Ok(());
+    };
+    let notes = notes
+        .as_object_mut()
+        .ok_or(SheetFileErr::Malformed("音符列表不是对象"))?;
+    let mut rescaled = json::Map::new();
+    for (_, group) in std::mem::take(notes) {
+        let json::Value::Array(group) = group else {
+            return Err(SheetFileErr::Malformed("音符分组不是数组"));
+        };
+        for mut note in group {
+            let start = tick_field(&note, "start")?;
+            let end = start.saturating_add(tick_field(&note, "length")?);
+            let start = rescale_tick(start, from, to);
+            let length = rescale_tick(end, from, to).saturating_sub(start).max(1);
+            note["start"] = start.into();
+            note["length"] = length.into();
+            if let json::Value::Array(group) = rescaled
+                .entry(start.to_string())
+                .or_insert_with(|| json::Value::Array(Vec::new()))
+            {
+                group.push(note);
+            }
+        }
+    }
+    *notes = rescaled;
+    Ok(())
+}
+
+/// Rescales the `[{ start, end }, [pattern ids]]` entries of a pattern track.
+fn rescale_track_ranges(track: &mut json::Value, from: u64, to: u64) -> Result<(), SheetFileErr> {
+    let Some(entries) = track.get_mut("patterns") else {
+        return Ok(());
+    };
+    let entries = entries
+        .as_array_mut()
+        .ok_or(SheetFileErr::Malformed("片段列表不是数组"))?;
+    for entry in entries {
+        let range = entry
+            .get_mut(0)
+            .ok_or(SheetFileErr::Malformed("片段缺少区间"))?;
+        let start = rescale_tick(tick_field(range, "start")?, from, to);
+        let end = rescale_tick(tick_field(range, "end")?, from, to).max(start + 1);
+        range["start"] = start.into();
+        range["end"] = end.into();
+    }
+    Ok(())
+}
+
+#[inline]
+fn tick_field(value: &json::Value, key: &str) -> Result<u64, SheetFileErr> {
+    value
+        .get(key)
+        .and_then(json::Value::as_u64)
+        .ok_or(SheetFileErr::Malformed("刻度不是整数"))
+}
+
 // LYN: EFrame Storage
 
 pub struct AppStorage;
@@ -0,0 +1,167 @@
+use std::{fmt::Display, io, path::Path, sync::Arc};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::model::{comm::SheetBatch, state::CentralState, track::SheetTrackTrait};
+
+// LYN: Offline Rendering
+
+#[derive(Debug, Error)]
+pub enum RenderErr {
+    #[error("无法编码发往 {0} 的指令")]
+    Encode(String),
+    #[error("无法写入渲染结果：{0}")]
+    Write(#[from] io::Error),
+    #[error("无法序列化渲染结果：{0}")]
+    Serialize(#[from] json::Error),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+impl Display for RenderFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderFormat::Jsonl => write!(f, "jsonl"),
+            RenderFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl RenderFormat {
+    pub fn variants() -> &'static [RenderFormat] {
+        &[RenderFormat::Jsonl, RenderFormat::Csv]
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::variants()
+            .iter()
+            .find(|format| format.to_string().eq_ignore_ascii_case(name))
+            .copied()
+    }
+    /// Guesses the format from the extension of the output file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_name)
+    }
+}
+
+/// A frame the sheet sends, as it would go over the wire.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedMessage {
+    pub tick: u64,
+    /// Seconds since the start of playback, rounded to microseconds.
+    pub time: f64,
+    pub target: String,
+    pub payload: String,
+}
+
+/// Plays the whole sheet once without waiting in between ticks, collecting what is sent.
+///
+/// Ticks are read like the sheet reader does, tracks in their display order and batched per
+/// target, and notes still sounding at the end are released after the last tick.
+pub fn render_sheet(state: &Arc<CentralState>) -> Result<Vec<RenderedMessage>, RenderErr> {
+    let ticks_per_beat = state.sheet_ticks_per_beat() as f64;
+    let limit = state.sheet_length_in_beats() * state.sheet_ticks_per_beat() - 1;
+    let tracks = state.sheet_tracks_ordered();
+
+    let mut rendered = Vec::new();
+    let mut time = 0.;
+    for tick in 0..=limit + 1 {
+        let msgs = tracks.iter().flat_map(|track| {
+            let track = track.read();
+            if tick <= limit {
+                track.msg_at(tick, state.clone())
+            } else {
+                track.release_at(tick, state.clone())
+            }
+        });
+        for batch in SheetBatch::group(tick, msgs) {
+            if let Some(msg) = render_batch(state, tick, time, batch)? {
+                rendered.push(msg);
+            }
+        }
+        let bpm = state
+            .metro_bpm_override()
+            .unwrap_or_else(|| state.sheet_bpm_at(tick));
+        time += 60. / (bpm * ticks_per_beat);
+    }
+    Ok(rendered)
+}
+
+pub fn write_rendered(
+    rendered: &[RenderedMessage],
+    format: RenderFormat,
+    mut writer: impl io::Write,
+) -> Result<(), RenderErr> {
+    match format {
+        RenderFormat::Jsonl => {
+            for msg in rendered {
+                writeln!(writer, "{}", json::to_string(msg)?)?;
+            }
+        }
+        RenderFormat::Csv => {
+            writeln!(writer, "tick,time,target,payload")?;
+            for msg in rendered {
+                writeln!(
+                    writer,
+                    "{},{:.6},{},{}",
+                    msg.tick,
+                    msg.time,
+                    csv_field(&msg.target),
+                    csv_field(&msg.payload)
+                )?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// LYN: Helpers
+
+/// Encodes the batch for its target, `None` if the target no longer exists.
+fn render_batch(
+    state: &CentralState,
+    tick: u64,
+    time: f64,
+    batch: SheetBatch,
+) -> Result<Option<RenderedMessage>, RenderErr> {
+    let Some(target) = state.sheet_get_comm_target(&batch.target_id) else {
+        return Ok(None);
+    };
+    let (name, format) = {
+        let target = target.read();
+        (target.name.clone(), target.format)
+    };
+    let payload = batch
+        .form_bytes(format)
+        .ok_or_else(|| RenderErr::Encode(name.clone()))?;
+    // binary payloads are written as hex
+    let payload = if format.is_binary() {
+        payload.iter().map(|byte| format!("{byte:02x}")).collect()
+    } else {
+        String::from_utf8_lossy(&payload).into_owned()
+    };
+    Ok(Some(RenderedMessage {
+        tick,
+        time: (time * 1e6).round() / 1e6,
+        target: name,
+        payload,
+    }))
+}
+
+/// Quotes the field if it contains anything CSV would otherwise split on.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use midly::{
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+    num::{u4, u7, u15, u24, u28},
+};
+use thiserror::Error;
+
+use crate::model::{
+    pattern::{
+        SheetPattern, SheetPatternTrait,
+        midi::{MidiNote, MidiPattern},
+    },
+    rescale_tick,
+    state::CentralState,
+    track::{SheetTrack, SheetTrackTrait},
+};
+
+// LYN: Standard MIDI File Import
+
+#[derive(Debug, Error)]
+pub enum SmfErr {
+    #[error("无法解析 MIDI 文件：{0}")]
+    Parse(#[from] midly::Error),
+    #[error("不支持以 SMPTE 时间码计时的 MIDI 文件")]
+    TimecodeTiming,
+    #[error("MIDI 文件中没有任何音符")]
+    NoNotes,
+    #[error("无法写入 MIDI 文件：{0}")]
+    Write(#[from] std::io::Error),
+    #[error("乐谱过长，事件间隔超出 MIDI 文件可表示的范围")]
+    TooLong,
+}
+
+/// Notes of a Standard MIDI File, grouped by track and channel.
+#[derive(Debug, Clone)]
+pub struct SmfImport {
+    pub groups: Vec<SmfNoteGroup>,
+    /// BPM of the first tempo event in the file.
+    pub bpm: Option<f64>,
+}
+
+/// Notes of a single channel in a single track, already on the tick grid of the sheet.
+#[derive(Debug, Clone)]
+pub struct SmfNoteGroup {
+    pub track_index: usize,
+    pub track_name: Option<String>,
+    pub channel: u8,
+    pub notes: Vec<MidiNote>,
+}
+
+impl SmfNoteGroup {
+    pub fn display_name(&self) -> String {
+        let track_name = self
+            .track_name
+            .clone()
+            .unwrap_or_else(|| format!("轨道 {}", self.track_index + 1));
+        format!("{track_name} (通道 {})", self.channel + 1)
+    }
+
+    /// Turns the group into a pattern, long enough to hold all of its notes.
+    pub fn to_pattern(&self, ticks_per_beat: u64) -> MidiPattern {
+        let mut pattern = MidiPattern::new();
+        pattern.name = self.display_name();
+        for note in self.notes.iter() {
+            pattern.add_note(MidiNote::new(
+                note.midicode,
+                note.strength,
+                note.start,
+                note.length,
+            ));
+        }
+        pattern.beats = pattern.min_beats(ticks_per_beat).max(1);
+        pattern
+    }
+}
+
+/// Reads a type 0/1 (or 2) Standard MIDI File, rescaling its timing to `ticks_per_beat`.
+pub fn import_smf(bytes: &[u8], ticks_per_beat: u64) -> Result<SmfImport, SmfErr> {
+    let smf = Smf::parse(bytes)?;
+    let file_tpb = match smf.header.timing {
+        Timing::Metrical(tpb) => tpb.as_int().max(1) as u64,
+        Timing::Timecode(..) => return Err(SmfErr::TimecodeTiming),
+    };
+
+    let mut bpm = None;
+    let mut groups = Vec::new();
+    for (track_index, track) in smf.tracks.iter().enumerate() {
+        let mut track_name = None;
+        let mut tick = 0;
+        // (channel, key) -> starting ticks and velocities of the sounding notes
+        let mut sounding: HashMap<(u8, u8), VecDeque<(u64, u8)>> = HashMap::new();
+        let mut channels: BTreeMap<u8, Vec<MidiNote>> = BTreeMap::new();
+
+        for event in track.iter() {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) if bpm.is_none() => {
+                    bpm = Some(60_000_000. / tempo.as_int().max(1) as f64);
+                }
+                TrackEventKind::Meta(MetaMessage::TrackName(name)) if track_name.is_none() => {
+                    let name = String::from_utf8_lossy(name).trim().to_string();
+                    if !name.is_empty() {
+                        track_name = Some(name);
+                    }
+                }
+                TrackEventKind::Midi { channel, message } => {
+                    let channel = channel.as_int();
+                    match message {
+                        MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            sounding
+                                .entry((channel, key.as_int()))
+                                .or_default()
+                                .push_back((tick, vel.as_int()));
+                        }
+                        MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                            if let Some((start, vel)) = sounding
+                                .get_mut(&(channel, key.as_int()))
+                                .and_then(VecDeque::pop_front)
+                            {
+                                channels.entry(channel).or_default().push(form_note(
+                                    key.as_int(),
+                                    vel,
+                                    start,
+                                    tick,
+                                    file_tpb,
+                                    ticks_per_beat,
+                                ));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // notes never released end with the track
+        for ((channel, key), starts) in sounding {
+            for (start, vel) in starts {
+                channels.entry(channel).or_default().push(form_note(
+                    key,
+                    vel,
+                    start,
+                    tick,
+                    file_tpb,
+                    ticks_per_beat,
+                ));
+            }
+        }
+
+        groups.extend(channels.into_iter().map(|(channel, notes)| SmfNoteGroup {
+            track_index,
+            track_name: track_name.clone(),
+            channel,
+            notes,
+        }));
+    }
+
+    if groups.is_empty() {
+        return Err(SmfErr::NoNotes);
+    }
+    Ok(SmfImport { groups, bpm })
+}
+
+// LYN: Standard MIDI File Export
+
+/// Tick resolution used for files when the one of the sheet does not fit into SMF.
+const FALLBACK_EXPORT_TICKS_PER_BEAT: u64 = 960;
+
+/// A MIDI event at an absolute tick, before being turned into delta timed [`TrackEvent`]s.
+#[derive(Debug, Clone, Copy)]
+enum ExportEvent<'a> {
+    NoteOff { key: u8 },
+    NoteOn { key: u8, vel: u8 },
+    Meta(MetaMessage<'a>),
+}
+
+impl ExportEvent<'_> {
+    /// Events at the same tick are ordered meta first, then note-offs, then note-ons, so a note
+    /// re-triggered right as it ends is not cut.
+    #[inline]
+    fn order(&self) -> u8 {
+        match self {
+            ExportEvent::Meta(_) => 0,
+            ExportEvent::NoteOff { .. } => 1,
+            ExportEvent::NoteOn { .. } => 2,
+        }
+    }
+}
+
+/// Writes the sheet as a type 1 Standard MIDI File.
+///
+/// The first track carries the tempo map, time signatures and markers, followed by one track for
+/// each pattern track with the MIDI patterns placed on it flattened to note on/off events.
+pub fn export_smf(state: &CentralState) -> Result<Vec<u8>, SmfErr> {
+    let sheet_tpb = state.sheet_ticks_per_beat();
+    let file_tpb = if sheet_tpb <= u15::max_value().as_int() as u64 {
+        sheet_tpb
+    } else {
+        FALLBACK_EXPORT_TICKS_PER_BEAT
+    };
+    let to_file_tick = |tick: u64| rescale_tick(tick, sheet_tpb, file_tpb);
+
+    // names have to outlive the tracks borrowing them
+    let mut conductor_texts = Vec::new();
+    let mut conductor = Vec::new();
+    conductor.push((0, ExportEvent::Meta(bpm_to_tempo(state.sheet_bpm()))));
+    if let Some(timeline) = state.sheet_timeline()
+        && let SheetTrack::Timeline(timeline) = &*timeline.read()
+    {
+        for (tick, bpm) in timeline.tempos_iter() {
+            conductor.push((to_file_tick(*tick), ExportEvent::Meta(bpm_to_tempo(*bpm))));
+        }
+        for (tick, sig) in timeline.signatures_iter() {
+            conductor.push((
+                to_file_tick(*tick),
+                ExportEvent::Meta(MetaMessage::TimeSignature(
+                    sig.numerator.min(u8::MAX as u64) as u8,
+                    sig.denominator.max(1).ilog2() as u8,
+                    24,
+                    8,
+                )),
+            ));
+        }
+        conductor_texts.extend(
+            timeline
+                .markers_iter()
+                .map(|(tick, name)| (to_file_tick(*tick), name.clone())),
+        );
+    }
+    conductor.extend(conductor_texts.iter().map(|(tick, name)| {
+        (
+            *tick,
+            ExportEvent::Meta(MetaMessage::Marker(name.as_bytes())),
+        )
+    }));
+
+    let mut tracks = Vec::new();
+    for track in state.sheet_tracks_ordered() {
+        let track = track.read();
+        let SheetTrack::Pattern(pattern_track) = &*track else {
+            continue;
+        };
+        let mut events = Vec::new();
+        for (range, patterns) in pattern_track.patterns_iter() {
+            for (_, pattern_id) in patterns {
+                let Some(pattern) = state.sheet_get_pattern(pattern_id) else {
+                    continue;
+                };
+                let pattern = pattern.read();
+                let SheetPattern::Midi(midi) = &*pattern else {
+                    continue;
+                };
+                // notes are cut where the pattern or its placed range ends, whichever comes first
+                let cut_tick = (midi.beats() * sheet_tpb).min(range.end - range.start);
+                for note in midi.notes_iter_owned().filter(|note| note.start < cut_tick) {
+                    let key = note.midicode.min(127);
+                    events.push((
+                        to_file_tick(range.start + note.start),
+                        ExportEvent::NoteOn {
+                            key,
+                            vel: strength_to_velocity(note.strength),
+                        },
+                    ));
+                    events.push((
+                        to_file_tick(range.start + note.end_tick().min(cut_tick)),
+                        ExportEvent::NoteOff { key },
+                    ));
+                }
+            }
+        }
+        tracks.push((track.name_ref().clone(), events));
+    }
+
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(u15::new(file_tpb as u16)),
+    ));
+    smf.tracks.push(to_track_events(conductor)?);
+    for (name, events) in tracks.iter() {
+        let mut events = events.clone();
+        events.push((
+            0,
+            ExportEvent::Meta(MetaMessage::TrackName(name.as_bytes())),
+        ));
+        smf.tracks.push(to_track_events(events)?);
+    }
+
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes)?;
+    Ok(bytes)
+}
+
+// LYN: Helpers
+
+/// Maps a MIDI velocity (`0..=127`) onto the full range of `MidiNote::strength`.
+#[inline]
+pub fn velocity_to_strength(vel: u8) -> u16 {
+    (vel.min(127) as u32 * u16::MAX as u32 / 127) as u16
+}
+
+/// Maps `MidiNote::strength` back onto a MIDI velocity, never `0` which would mean note-off.
+#[inline]
+pub fn strength_to_velocity(strength: u16) -> u8 {
+    ((strength as u32 * 127 / u16::MAX as u32) as u8).max(1)
+}
+
+/// Tempos slower than the 24-bit microseconds per beat can hold (about 3.58 bpm) are clamped.
+#[inline]
+fn bpm_to_tempo(bpm: f64) -> MetaMessage<'static> {
+    let micros = (60_000_000. / bpm.max(1.)).round() as u32;
+    MetaMessage::Tempo(u24::new(micros.min(u24::max_value().as_int())))
+}
+
+/// Sorts absolute timed events into a delta timed track, closed by an end of track event.
+fn to_track_events(mut events: Vec<(u64, ExportEvent<'_>)>) -> Result<Vec<TrackEvent<'_>>, SmfErr> {
+    events.sort_by_key(|(tick, event)| (*tick, event.order()));
+    let mut last_tick = 0;
+    let mut track = Vec::with_capacity(events.len() + 1);
+    for (tick, event) in events {
+        let kind = match event {
+            ExportEvent::NoteOff { key } => TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::NoteOff {
+                    key: u7::new(key),
+                    vel: u7::new(0),
+                },
+            },
+            ExportEvent::NoteOn { key, vel } => TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(vel),
+                },
+            },
+            ExportEvent::Meta(meta) => TrackEventKind::Meta(meta),
+        };
+        let delta = u32::try_from(tick - last_tick)
+            .ok()
+            .filter(|delta| *delta <= u28::max_value().as_int())
+            .ok_or(SmfErr::TooLong)?;
+        track.push(TrackEvent {
+            delta: u28::new(delta),
+            kind,
+        });
+        last_tick = tick;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    Ok(track)
+}
+
+fn form_note(key: u8, vel: u8, start: u64, end: u64, from: u64, to: u64) -> MidiNote {
+    let start = rescale_tick(start, from, to);
+    let end = rescale_tick(end, from, to);
+    MidiNote::new(
+        key,
+        velocity_to_strength(vel),
+        start,
+        end.saturating_sub(start).max(1),
+    )
+}
@@ -1,27 +1,42 @@
 use std::{
-    io::Write,
-    net::{SocketAddr, TcpStream},
+    hash::Hash,
+    io::{self, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
     num::NonZero,
     ops,
-    sync::Arc,
+    sync::{Arc, mpsc},
     time::Duration,
 };
 
-use dashmap::{DashMap, DashSet, mapref::one::Ref};
+use dashmap::{DashMap, DashSet};
 use log::trace;
 use lyn_util::{comm::Format, egui::LynId, types::WithId};
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
 
 use crate::{
     app::PlayerContext,
     model::{
-        comm::{CommStream, CommStreamErr, CommTarget},
-        pattern::{SheetPattern, SheetPatternTrait, SheetPatternType, midi::MidiPattern},
-        track::{SheetTrack, SheetTrackType, pattern::PatternTrack},
+        DEFAULT_TICK_PER_BEAT,
+        comm::{CommHealth, CommStream, CommStreamErr, CommTarget, SendQueue, SheetBatch},
+        history::{History, HistoryCommand, HistoryStep, PendingStep, SheetProps},
+        loopback::LoopbackSink,
+        pattern::{
+            SheetPattern, SheetPatternTrait, SheetPatternType, curve::CurvePattern,
+            event::EventPattern, midi::MidiPattern,
+        },
+        persistence::{SheetFileErr, unwrap_sheet_file, wrap_sheet_value},
+        rescale_tick,
+        track::{SheetTrack, SheetTrackType, pattern::PatternTrack, timeline::TimelineTrack},
+        traffic::{TrafficKind, TrafficLog},
+    },
+    routines::{
+        RoutineId,
+        metronome::{MetroEvent, TickJitter},
+        remote_control::{RemoteControlConfig, RemoteStatus},
     },
-    routines::{RoutineId, metronome::TICK_PER_BEAT},
 };
 
 // LYN: Model Id
@@ -59,12 +74,15 @@ pub struct CentralState {
     app: App,
     metro: Metronome,
     sheet: Sheet,
+    history: RwLock<History>,
 }
 
 #[derive(Debug)]
 pub struct UiState {
     pub track_editor_size_per_beat: RwLock<f32>,
     pub pattern_editor_size_per_beat: RwLock<f32>,
+    /// Number of snapping steps per beat in the editors.
+    pub snap_division: RwLock<u64>,
 }
 
 impl UiState {
@@ -73,27 +91,66 @@ impl UiState {
 
     pub const STORAGE_KEY_TRACK_SPB: &str = "track-size-per-beat";
     pub const STORAGE_KEY_PATTERN_SPB: &str = "pattern-size-per-beat";
+    pub const STORAGE_KEY_SNAP_DIVISION: &str = "snap-division";
+
+    pub const DEFAULT_SNAP_DIVISION: u64 = 4;
+    pub const SNAP_DIVISIONS: [u64; 8] = [1, 2, 3, 4, 6, 8, 12, 16];
+
+    /// Human readable note value of a snap division, e.g. `4` is `1/16` and `3` is `1/8T`.
+    pub fn snap_division_label(division: u64) -> String {
+        if division.is_multiple_of(3) {
+            format!("1/{}T", division / 3 * 8)
+        } else {
+            format!("1/{}", division * 4)
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct App {
     err_modal_message: RwLock<Option<String>>,
+    notice_modal_message: RwLock<Option<String>>,
     selected_pattern: RwLock<Option<PatternId>>,
     player_context: RwLock<PlayerContext>,
     comm_stream: DashMap<TargetId, CommStream>,
     comm_stream_connecting: DashSet<TargetId>,
+    comm_queues: DashMap<TargetId, Arc<SendQueue>>,
+    comm_health: DashMap<TargetId, CommHealth>,
+    traffic: RwLock<TrafficLog>,
+    loopback: RwLock<LoopbackSink>,
+    /// How far ahead of the metronome instructions are sent, time stamped.
+    comm_lookahead: RwLock<Duration>,
+    remote_config: RwLock<RemoteControlConfig>,
+    remote_status: RwLock<RemoteStatus>,
+}
+
+impl App {
+    pub const MAX_COMM_LOOKAHEAD: Duration = Duration::from_secs(1);
 }
 
 #[derive(Debug)]
 pub struct Metronome {
     playing: RwLock<bool>,
+    /// Whether playback starts over at the tick limit, or stops there.
+    looping: RwLock<bool>,
+    /// Whether playback stopped by reaching the tick limit, cleared once it starts again.
+    finished: RwLock<bool>,
+    /// BPM taking precedence over the sheet and its tempo map.
+    bpm_override: RwLock<Option<f64>>,
+    jitter: RwLock<TickJitter>,
     curr_tick: RwLock<u64>,
-    tick_memory: DashMap<RoutineId, u64>,
+    /// Whether the current tick was already pushed to the subscribers, so resuming playback
+    /// does not read it twice.
+    tick_delivered: RwLock<bool>,
+    subscribers: DashMap<RoutineId, mpsc::Sender<MetroEvent>>,
+    /// Ticks each routine fell behind on since playback started.
+    lagged_ticks: DashMap<RoutineId, u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Sheet {
     bpm: RwLock<f64>,
+    ticks_per_beat: RwLock<u64>,
     length_in_beats: RwLock<NonZero<u64>>,
 
     tracks: DashMap<TrackId, Arc<RwLock<SheetTrack>>>,
@@ -105,35 +162,62 @@ pub struct Sheet {
     targets_ordering: RwLock<Vec<TargetId>>,
 }
 
+impl Sheet {
+    pub const TICKS_PER_BEAT_CHOICES: [u64; 6] = [24, 48, 96, 192, 480, 960];
+    /// Largest length in beats whose ticks still fit at the finest tick resolution.
+    pub const MAX_BEATS: u64 =
+        u64::MAX / Self::TICKS_PER_BEAT_CHOICES[Self::TICKS_PER_BEAT_CHOICES.len() - 1];
+
+    fn new() -> Self {
+        Self {
+            bpm: RwLock::new(130.),
+            ticks_per_beat: RwLock::new(DEFAULT_TICK_PER_BEAT),
+            length_in_beats: RwLock::new(NonZero::<u64>::MIN),
+
+            tracks: DashMap::new(),
+            patterns: DashMap::new(),
+            targets: DashMap::new(),
+
+            tracks_ordering: RwLock::new(Vec::new()),
+            patterns_ordering: RwLock::new(Vec::new()),
+            targets_ordering: RwLock::new(Vec::new()),
+        }
+    }
+}
+
 impl CentralState {
     pub fn init() -> Self {
         let app = App {
             err_modal_message: RwLock::new(None),
+            notice_modal_message: RwLock::new(None),
             selected_pattern: RwLock::new(None),
             player_context: RwLock::new(PlayerContext::Sheet),
             comm_stream: DashMap::new(),
             comm_stream_connecting: DashSet::new(),
+            comm_queues: DashMap::new(),
+            comm_health: DashMap::new(),
+            traffic: RwLock::new(TrafficLog::default()),
+            loopback: RwLock::new(LoopbackSink::default()),
+            comm_lookahead: RwLock::new(Duration::ZERO),
+            remote_config: RwLock::new(RemoteControlConfig::default()),
+            remote_status: RwLock::new(RemoteStatus::default()),
         };
         let ui = UiState {
             track_editor_size_per_beat: RwLock::new(UiState::MIN_SIZE_PER_BEAT),
             pattern_editor_size_per_beat: RwLock::new(UiState::MIN_SIZE_PER_BEAT),
+            snap_division: RwLock::new(UiState::DEFAULT_SNAP_DIVISION),
         };
-        let sheet = Sheet {
-            bpm: RwLock::new(130.),
-            length_in_beats: RwLock::new(NonZero::<u64>::MIN),
-
-            tracks: DashMap::new(),
-            patterns: DashMap::new(),
-            targets: DashMap::new(),
-
-            tracks_ordering: RwLock::new(Vec::new()),
-            patterns_ordering: RwLock::new(Vec::new()),
-            targets_ordering: RwLock::new(Vec::new()),
-        };
+        let sheet = Sheet::new();
         let metro = Metronome {
             playing: RwLock::new(false),
+            looping: RwLock::new(true),
+            finished: RwLock::new(false),
+            bpm_override: RwLock::new(None),
+            jitter: RwLock::new(TickJitter::default()),
             curr_tick: RwLock::new(0),
-            tick_memory: DashMap::default(),
+            tick_delivered: RwLock::new(false),
+            subscribers: DashMap::default(),
+            lagged_ticks: DashMap::default(),
         };
 
         Self {
@@ -142,6 +226,7 @@ impl CentralState {
             app,
             metro,
             sheet,
+            history: RwLock::new(History::default()),
         }
     }
 }
@@ -176,23 +261,115 @@ impl CentralState {
     pub fn app_get_err_msg(&self) -> RwLockReadGuard<'_, Option<String>> {
         self.app.err_modal_message.read()
     }
+    pub fn app_set_notice_msg(&self, msg: Option<String>) {
+        *self.app.notice_modal_message.write() = msg;
+    }
+    pub fn app_get_notice_msg(&self) -> RwLockReadGuard<'_, Option<String>> {
+        self.app.notice_modal_message.read()
+    }
+    /// Returns how far ahead of the metronome instructions are sent, zero for as they happen.
+    pub fn comm_lookahead(&self) -> Duration {
+        *self.app.comm_lookahead.read()
+    }
+    pub fn comm_set_lookahead(&self, lookahead: Duration) {
+        *self.app.comm_lookahead.write() = lookahead.min(App::MAX_COMM_LOOKAHEAD);
+    }
+    pub fn loopback_sink(&self) -> RwLockReadGuard<'_, LoopbackSink> {
+        self.app.loopback.read()
+    }
+    pub fn loopback_sink_mut(&self) -> RwLockWriteGuard<'_, LoopbackSink> {
+        self.app.loopback.write()
+    }
+    pub fn traffic_log(&self) -> RwLockReadGuard<'_, TrafficLog> {
+        self.app.traffic.read()
+    }
+    pub fn traffic_log_mut(&self) -> RwLockWriteGuard<'_, TrafficLog> {
+        self.app.traffic.write()
+    }
+    pub fn traffic_record_batch(&self, batch: &SheetBatch, delivered: bool) {
+        if !self.app.traffic.read().wants_instructions() {
+            return;
+        }
+        // formatted up front, so writers hold the log for as short as possible
+        let target = self.comm_target_name(&batch.target_id);
+        let kinds = TrafficKind::from_batch(batch, delivered).collect::<Vec<_>>();
+        let mut log = self.app.traffic.write();
+        for kind in kinds {
+            log.record(target.clone(), kind);
+        }
+    }
+    pub fn traffic_record_event(&self, id: &TargetId, message: String) {
+        let target = self.comm_target_name(id);
+        self.app
+            .traffic
+            .write()
+            .record(target, TrafficKind::Connection { message });
+    }
+    fn comm_target_name(&self, id: &TargetId) -> String {
+        self.sheet
+            .targets
+            .get(id)
+            .map(|target| target.read().name.clone())
+            .unwrap_or_default()
+    }
+    pub fn remote_config(&self) -> RemoteControlConfig {
+        self.app.remote_config.read().clone()
+    }
+    pub fn remote_config_mut(&self) -> RwLockWriteGuard<'_, RemoteControlConfig> {
+        self.app.remote_config.write()
+    }
+    pub fn remote_status(&self) -> RemoteStatus {
+        self.app.remote_status.read().clone()
+    }
+    pub fn remote_set_status(&self, status: RemoteStatus) {
+        *self.app.remote_status.write() = status;
+    }
+    pub fn comm_get_queue(&self, id: &TargetId) -> Option<Arc<SendQueue>> {
+        self.app.comm_queues.get(id).map(|entry| entry.clone())
+    }
+    /// Returns the send queue of the target, and whether it was just created.
+    pub fn comm_open_queue(&self, id: &TargetId) -> (Arc<SendQueue>, bool) {
+        if let Some(queue) = self.comm_get_queue(id) {
+            return (queue, false);
+        }
+        let queue = Arc::new(SendQueue::default());
+        self.app.comm_queues.insert(id.clone(), queue.clone());
+        (queue, true)
+    }
+    /// Forgets the send queue of the target, unless it was already replaced by a new one.
+    pub fn comm_remove_queue(&self, id: &TargetId, queue: &Arc<SendQueue>) {
+        self.app
+            .comm_queues
+            .remove_if(id, |_, current| Arc::ptr_eq(current, queue));
+    }
+    /// Closes the send queues of targets no longer in the sheet, and forgets their health.
+    pub fn comm_close_stale_queues(&self) {
+        self.app.comm_queues.retain(|id, queue| {
+            let alive = self.sheet.targets.contains_key(id);
+            if !alive {
+                queue.close();
+            }
+            alive
+        });
+        self.app
+            .comm_health
+            .retain(|id, _| self.sheet.targets.contains_key(id));
+    }
     pub fn comm_stream_exists(&self, id: &TargetId) -> bool {
         self.app.comm_stream.try_get(id).is_present()
     }
-    pub fn comm_get_stream(&self, id: &TargetId) -> Option<Ref<'_, TargetId, CommStream>> {
-        self.app.comm_stream.get(id)
-    }
     pub fn comm_drop_stream(&self, id: &TargetId) {
         self.app.comm_stream.remove(id);
     }
+    /// Connects to the target, replacing its stream if it was already connected.
     pub fn comm_connect_stream_blocking(
         &self,
         id: TargetId,
         addr: &str,
         format: Format,
-    ) -> Option<Ref<'_, TargetId, CommStream>> {
+    ) -> Result<(), CommStreamErr> {
         if self.app.comm_stream_connecting.get(&id).is_some() {
-            return None;
+            return Err(CommStreamErr::Connecting);
         }
         self.app.comm_stream_connecting.insert(id.clone());
 
@@ -210,30 +387,53 @@ impl CentralState {
             state: self,
             id: &id,
         };
-        let addr: SocketAddr = addr.parse().ok()?;
+        let parse_addr = || {
+            addr.parse::<SocketAddr>()
+                .map_err(|_| CommStreamErr::InvalidAddr(addr.to_string()))
+        };
         let timeout = Duration::from_secs(3);
 
-        self.app.comm_stream.get(&id);
-        trace!("old comm stream removed");
         let stream = match format {
             Format::WsBasedJson => {
-                let tcp_stream = TcpStream::connect_timeout(&addr, timeout).ok()?;
-                tcp_stream.set_read_timeout(Some(timeout)).ok()?;
-                tcp_stream.set_write_timeout(Some(timeout)).ok()?;
+                let addr = parse_addr()?;
+                let tcp_stream = TcpStream::connect_timeout(&addr, timeout)?;
+                tcp_stream.set_read_timeout(Some(timeout))?;
+                tcp_stream.set_write_timeout(Some(timeout))?;
+                set_keepalive(&tcp_stream)?;
 
                 trace!("trying to connect (websocket)");
-                let (ws, _) = ws::client(format!("ws://{}", addr), tcp_stream).ok()?;
+                let (ws, _) = ws::client(format!("ws://{}", addr), tcp_stream)
+                    .map_err(|e| CommStreamErr::Handshake(e.to_string()))?;
+                // only pongs are read, without holding up sending
+                ws.get_ref().set_read_timeout(Some(PONG_READ_TIMEOUT))?;
                 CommStream::WebSocket(Box::new(ws))
             }
-            Format::TcpBasedOsc => {
+            Format::TcpBasedOsc | Format::TcpBasedFudi => {
                 trace!("trying to connect (tcp)");
-                let stream = TcpStream::connect_timeout(&addr, timeout).ok()?;
+                let addr = parse_addr()?;
+                let stream = TcpStream::connect_timeout(&addr, timeout)?;
+                set_keepalive(&stream)?;
                 CommStream::TcpStream(stream)
             }
+            Format::UdpOsc | Format::UdpFudi => {
+                trace!("binding socket (udp)");
+                let addr = parse_addr()?;
+                let local: SocketAddr = if addr.is_ipv4() {
+                    (Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                // connecting only fixes the destination, nothing is sent
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                CommStream::UdpSocket(socket)
+            }
+            // nothing to connect to, the address is not used
+            Format::Loopback => CommStream::Loopback,
         };
         self.app.comm_stream.insert(id.clone(), stream);
         trace!("new comm stream inserted");
-        self.app.comm_stream.get(&id)
+        Ok(())
     }
     pub fn comm_send_data_blocking(
         &self,
@@ -246,9 +446,65 @@ impl CentralState {
         match entry.value_mut() {
             CommStream::WebSocket(ws) => ws.send(data.into())?,
             CommStream::TcpStream(stream) => stream.write_all(&data)?,
+            CommStream::UdpSocket(socket) => {
+                socket.send(&data)?;
+            }
+            CommStream::Loopback => {
+                let target = self.comm_target_name(id);
+                let tick_secs = self.metro_tick_interval().as_secs_f64();
+                self.app
+                    .loopback
+                    .write()
+                    .receive(&target, &data, tick_secs)?;
+            }
         }
         Ok(())
     }
+    /// Pings the target, returns `false` if its format has no heartbeat other than keepalive.
+    pub fn comm_send_ping(&self, id: &TargetId) -> Result<bool, CommStreamErr> {
+        let Some(mut entry) = self.app.comm_stream.get_mut(id) else {
+            return Err(CommStreamErr::NoCommStream);
+        };
+        let CommStream::WebSocket(ws) = entry.value_mut() else {
+            return Ok(false);
+        };
+        ws.send(ws::Message::Ping(Default::default()))?;
+        Ok(true)
+    }
+    /// Reads what the target sent, returns whether a pong was among it.
+    pub fn comm_poll_pong(&self, id: &TargetId) -> Result<bool, CommStreamErr> {
+        let Some(mut entry) = self.app.comm_stream.get_mut(id) else {
+            return Err(CommStreamErr::NoCommStream);
+        };
+        let CommStream::WebSocket(ws) = entry.value_mut() else {
+            return Ok(false);
+        };
+        loop {
+            match ws.read() {
+                Ok(ws::Message::Pong(_)) => return Ok(true),
+                Ok(_) => {}
+                Err(ws::Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(false);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    pub fn comm_health(&self, id: &TargetId) -> CommHealth {
+        self.app
+            .comm_health
+            .get(id)
+            .map(|health| health.clone())
+            .unwrap_or_default()
+    }
+    pub fn comm_update_health(&self, id: &TargetId, update: impl FnOnce(&mut CommHealth)) {
+        update(&mut self.app.comm_health.entry(id.clone()).or_default());
+    }
 }
 
 impl CentralState {
@@ -256,8 +512,72 @@ impl CentralState {
         *self.sheet.bpm.read()
     }
     pub fn sheet_bpm_mut(&self) -> RwLockWriteGuard<'_, f64> {
+        self.history_touch_props();
         self.sheet.bpm.write()
     }
+    pub fn sheet_ticks_per_beat(&self) -> u64 {
+        *self.sheet.ticks_per_beat.read()
+    }
+    /// Changes the tick resolution of the sheet, rescaling every tick based item in it.
+    ///
+    /// The resolution lock is not held while rescaling, as the sheet reader takes it while
+    /// holding track locks.
+    pub fn sheet_set_ticks_per_beat(&self, ticks_per_beat: u64) {
+        let curr = self.sheet_ticks_per_beat();
+        if curr == ticks_per_beat {
+            return;
+        }
+        self.history_transaction(|| {
+            self.history_touch_props();
+            self.sheet_rescale_ticks(curr, ticks_per_beat);
+            *self.sheet.ticks_per_beat.write() = ticks_per_beat;
+        });
+        let mut tick = self.metro.curr_tick.write();
+        *tick = rescale_tick(*tick, curr, ticks_per_beat);
+    }
+    fn sheet_rescale_ticks(&self, from: u64, to: u64) {
+        let patterns = self
+            .sheet
+            .patterns
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()));
+        for (id, pattern) in patterns.collect::<Vec<_>>() {
+            self.history_touch_pattern(&id);
+            pattern.write().rescale_ticks(from, to);
+        }
+        let tracks = self
+            .sheet
+            .tracks
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()));
+        for (id, track) in tracks.collect::<Vec<_>>() {
+            self.history_touch_track(&id);
+            track.write().rescale_ticks(from, to);
+        }
+    }
+    /// Returns the snapping step of the editors in ticks.
+    pub fn sheet_tick_snap(&self) -> u64 {
+        (self.sheet_ticks_per_beat() / *self.ui.snap_division.read()).max(1)
+    }
+    /// Returns the BPM at the given tick, following the tempo map of the timeline track.
+    pub fn sheet_bpm_at(&self, tick: u64) -> f64 {
+        self.sheet_timeline()
+            .and_then(|track| match &*track.read() {
+                SheetTrack::Timeline(timeline) => timeline.bpm_at(tick),
+                _ => None,
+            })
+            .unwrap_or_else(|| self.sheet_bpm())
+    }
+    /// Returns the starting tick of every bar up to (and including) `until`.
+    pub fn sheet_bar_ticks(&self, until: u64) -> Vec<u64> {
+        let ticks_per_beat = self.sheet_ticks_per_beat();
+        self.sheet_timeline()
+            .and_then(|track| match &*track.read() {
+                SheetTrack::Timeline(timeline) => Some(timeline.bar_ticks(until, ticks_per_beat)),
+                _ => None,
+            })
+            .unwrap_or_else(|| TimelineTrack::new().bar_ticks(until, ticks_per_beat))
+    }
 
     pub fn sheet_comm_targets_iter(
         &self,
@@ -267,19 +587,27 @@ impl CentralState {
     pub fn sheet_add_comm_target(&self) -> WithId<TargetId, Arc<RwLock<CommTarget>>> {
         let target = Arc::new(RwLock::new(CommTarget::default()));
         let id: TargetId = LynId::obtain_string().into();
-        self.sheet.targets.insert(id.clone(), target.clone());
-        self.sheet.targets_ordering.write().push(id.clone());
+        self.history_transaction(|| {
+            self.history_touch_props();
+            self.history_touch_target(&id);
+            self.sheet.targets.insert(id.clone(), target.clone());
+            self.sheet.targets_ordering.write().push(id.clone());
+        });
         WithId::new(id, target)
     }
     pub fn sheet_del_comm_target(
         &self,
         id: &TargetId,
     ) -> Option<WithId<TargetId, Arc<RwLock<CommTarget>>>> {
-        self.sheet.targets_ordering.write().retain(|tid| tid != id);
-        self.sheet
-            .targets
-            .remove(id)
-            .map(|entry| WithId::new(entry.0, entry.1))
+        self.history_transaction(|| {
+            self.history_touch_props();
+            self.history_touch_target(id);
+            self.sheet.targets_ordering.write().retain(|tid| tid != id);
+            self.sheet
+                .targets
+                .remove(id)
+                .map(|entry| WithId::new(entry.0, entry.1))
+        })
     }
     pub fn sheet_get_comm_target(&self, id: &TargetId) -> Option<Arc<RwLock<CommTarget>>> {
         self.sheet.targets.get(id).map(|item| item.clone())
@@ -289,6 +617,7 @@ impl CentralState {
         self.sheet.length_in_beats.read().get()
     }
     pub fn sheet_length_in_beats_mut(&self) -> RwLockWriteGuard<'_, NonZero<u64>> {
+        self.history_touch_props();
         self.sheet.length_in_beats.write()
     }
 
@@ -296,12 +625,25 @@ impl CentralState {
         &self,
         pattern_type: SheetPatternType,
     ) -> WithId<PatternId, Arc<RwLock<SheetPattern>>> {
-        let pat = Arc::new(RwLock::new(match pattern_type {
+        self.sheet_insert_pattern(match pattern_type {
             SheetPatternType::Midi => SheetPattern::Midi(MidiPattern::new()),
-        }));
+            SheetPatternType::Curve => SheetPattern::Curve(CurvePattern::new()),
+            SheetPatternType::Event => SheetPattern::Event(EventPattern::new()),
+        })
+    }
+    /// Adds an already built pattern to the end of the pattern list.
+    pub fn sheet_insert_pattern(
+        &self,
+        pattern: SheetPattern,
+    ) -> WithId<PatternId, Arc<RwLock<SheetPattern>>> {
+        let pat = Arc::new(RwLock::new(pattern));
         let id: PatternId = LynId::obtain_string().into();
-        self.sheet.patterns.insert(id.clone(), pat.clone());
-        self.sheet.patterns_ordering.write().push(id.clone());
+        self.history_transaction(|| {
+            self.history_touch_props();
+            self.history_touch_pattern(&id);
+            self.sheet.patterns.insert(id.clone(), pat.clone());
+            self.sheet.patterns_ordering.write().push(id.clone());
+        });
         WithId::new(id, pat)
     }
 
@@ -309,11 +651,15 @@ impl CentralState {
         &self,
         id: &PatternId,
     ) -> Option<WithId<PatternId, Arc<RwLock<SheetPattern>>>> {
-        self.sheet.patterns_ordering.write().retain(|pid| pid != id);
-        self.sheet
-            .patterns
-            .remove(id)
-            .map(|entry| WithId::new(entry.0, entry.1))
+        self.history_transaction(|| {
+            self.history_touch_props();
+            self.history_touch_pattern(id);
+            self.sheet.patterns_ordering.write().retain(|pid| pid != id);
+            self.sheet
+                .patterns
+                .remove(id)
+                .map(|entry| WithId::new(entry.0, entry.1))
+        })
     }
 
     pub fn sheet_get_pattern(&self, id: &PatternId) -> Option<Arc<RwLock<SheetPattern>>> {
@@ -332,10 +678,15 @@ impl CentralState {
     ) -> WithId<TrackId, Arc<RwLock<SheetTrack>>> {
         let track = Arc::new(RwLock::new(match track_type {
             SheetTrackType::Pattern => SheetTrack::Pattern(PatternTrack::new()),
+            SheetTrackType::Timeline => SheetTrack::Timeline(TimelineTrack::new()),
         }));
         let id: TrackId = LynId::obtain_string().into();
-        self.sheet.tracks.insert(id.clone(), track.clone());
-        self.sheet.tracks_ordering.write().push(id.clone());
+        self.history_transaction(|| {
+            self.history_touch_props();
+            self.history_touch_track(&id);
+            self.sheet.tracks.insert(id.clone(), track.clone());
+            self.sheet.tracks_ordering.write().push(id.clone());
+        });
         WithId::new(id, track)
     }
 
@@ -343,36 +694,80 @@ impl CentralState {
         &self,
         id: &TrackId,
     ) -> Option<WithId<TrackId, Arc<RwLock<SheetTrack>>>> {
-        self.sheet.tracks_ordering.write().retain(|tid| tid != id);
-        self.sheet
-            .tracks
-            .remove(id)
-            .map(|entry| WithId::new(entry.0, entry.1))
+        self.history_transaction(|| {
+            self.history_touch_props();
+            self.history_touch_track(id);
+            self.sheet.tracks_ordering.write().retain(|tid| tid != id);
+            self.sheet
+                .tracks
+                .remove(id)
+                .map(|entry| WithId::new(entry.0, entry.1))
+        })
     }
 
     pub fn sheet_get_track(&self, id: &TrackId) -> Option<Arc<RwLock<SheetTrack>>> {
         self.sheet.tracks.get(id).map(|item| item.clone())
     }
 
-    pub fn sheet_tracks_iter(&self) -> dashmap::iter::Iter<'_, TrackId, Arc<RwLock<SheetTrack>>> {
-        self.sheet.tracks.iter()
+    /// Returns the topmost timeline track, which drives the tempo map of the sheet.
+    pub fn sheet_timeline(&self) -> Option<Arc<RwLock<SheetTrack>>> {
+        self.sheet
+            .tracks_ordering
+            .read()
+            .iter()
+            .filter_map(|id| self.sheet_get_track(id))
+            .find(|track| matches!(*track.read(), SheetTrack::Timeline(_)))
+    }
+
+    /// Returns the tracks in their display order, which is also the order they are read in.
+    pub fn sheet_tracks_ordered(&self) -> Vec<Arc<RwLock<SheetTrack>>> {
+        // tracks may query the sheet while being read, so the ordering lock is not held
+        let ordering = self.sheet.tracks_ordering.read().clone();
+        ordering
+            .iter()
+            .filter_map(|id| self.sheet_get_track(id))
+            .collect()
     }
 
     pub fn sheet_patterns_ordering_mut(&self) -> RwLockWriteGuard<'_, Vec<PatternId>> {
+        self.history_touch_props();
         self.sheet.patterns_ordering.write()
     }
+    pub fn sheet_tracks_ordering(&self) -> RwLockReadGuard<'_, Vec<TrackId>> {
+        self.sheet.tracks_ordering.read()
+    }
     pub fn sheet_tracks_ordering_mut(&self) -> RwLockWriteGuard<'_, Vec<TrackId>> {
+        self.history_touch_props();
         self.sheet.tracks_ordering.write()
     }
+    pub fn sheet_targets_ordering(&self) -> RwLockReadGuard<'_, Vec<TargetId>> {
+        self.sheet.targets_ordering.read()
+    }
     pub fn sheet_targets_ordering_mut(&self) -> RwLockWriteGuard<'_, Vec<TargetId>> {
+        self.history_touch_props();
         self.sheet.targets_ordering.write()
     }
     pub fn sheet_to_json_string_pretty(&self) -> Result<String, json::Error> {
-        json::to_string_pretty(&self.sheet)
+        json::to_string_pretty(&wrap_sheet_value(json::to_value(&self.sheet)?))
+    }
+    /// Loads a sheet file of any known format version, the current sheet is left untouched on
+    /// failure.
+    pub fn sheet_from_json_str(&self, s: &str) -> Result<(), SheetFileErr> {
+        let value = unwrap_sheet_file(s)?;
+        let sheet: Sheet = json::from_value(value).map_err(SheetFileErr::Corrupt)?;
+        self.sheet_replace(sheet);
+        self.history_clear();
+        Ok(())
     }
-    pub fn sheet_from_json_str(&self, s: &str) -> Result<(), json::Error> {
-        let sheet: Sheet = json::from_str(s)?;
+    /// Replaces the sheet with an empty one.
+    pub fn sheet_reset(&self) {
+        self.sheet_replace(Sheet::new());
+        self.history_clear();
+    }
+    fn sheet_replace(&self, sheet: Sheet) {
+        self.metro_make_stop();
         *self.sheet.bpm.write() = *sheet.bpm.read();
+        *self.sheet.ticks_per_beat.write() = *sheet.ticks_per_beat.read();
         *self.sheet.length_in_beats.write() = *sheet.length_in_beats.read();
         self.sheet.tracks.clear();
         for entry in sheet.tracks.iter() {
@@ -426,7 +821,198 @@ impl CentralState {
         for id in target_id_set {
             self.sheet.targets_ordering.write().push(id);
         }
-        Ok(())
+    }
+}
+
+impl CentralState {
+    /// Identifies the current content of the sheet, used to tell whether it has unsaved edits.
+    pub fn history_revision(&self) -> u64 {
+        self.history.read().revision()
+    }
+    pub fn history_can_undo(&self) -> bool {
+        self.history.read().can_undo()
+    }
+    pub fn history_can_redo(&self) -> bool {
+        self.history.read().can_redo()
+    }
+    /// Marks the beginning of an editing gesture, edits made until it settles end up in one
+    /// undo step, so a whole drag is undone at once.
+    pub fn history_begin_gesture(&self) {
+        self.history.write().begin_gesture();
+    }
+    /// Marks the end of the editing gesture, recording what it changed as an undo step.
+    pub fn history_settle_gesture(&self) {
+        let step = self.history.write().end_gesture();
+        self.history_close(step);
+    }
+    /// Runs `edit` as one undo step, or as part of the pending gesture if there is one.
+    ///
+    /// Edits made outside of editing gestures (imports, background routines, ...) must run in a
+    /// transaction to be recorded.
+    pub fn history_transaction<R>(&self, edit: impl FnOnce() -> R) -> R {
+        self.history.write().begin_transaction();
+        let result = edit();
+        let step = self.history.write().end_transaction();
+        self.history_close(step);
+        result
+    }
+    /// Captures the plain sheet values before they are edited, does nothing outside of a gesture
+    /// or transaction.
+    pub fn history_touch_props(&self) {
+        if self
+            .history
+            .read()
+            .pending()
+            .is_none_or(|step| step.props_touched())
+        {
+            return;
+        }
+        let props = self.sheet_props();
+        if let Some(step) = self.history.write().pending_mut() {
+            step.touch_props(props);
+        }
+    }
+    /// Captures a track before it is edited (or added), does nothing outside of a gesture or
+    /// transaction. The track must not be locked by the caller.
+    pub fn history_touch_track(&self, id: &TrackId) {
+        if self
+            .history
+            .read()
+            .pending()
+            .is_none_or(|step| step.track_touched(id))
+        {
+            return;
+        }
+        let track = self.sheet_get_track(id).map(|track| track.read().clone());
+        if let Some(step) = self.history.write().pending_mut() {
+            step.touch_track(id.clone(), track);
+        }
+    }
+    /// Captures a pattern before it is edited (or added), does nothing outside of a gesture or
+    /// transaction. The pattern must not be locked by the caller.
+    pub fn history_touch_pattern(&self, id: &PatternId) {
+        if self
+            .history
+            .read()
+            .pending()
+            .is_none_or(|step| step.pattern_touched(id))
+        {
+            return;
+        }
+        let pattern = self.sheet_get_pattern(id).map(|pat| pat.read().clone());
+        if let Some(step) = self.history.write().pending_mut() {
+            step.touch_pattern(id.clone(), pattern);
+        }
+    }
+    /// Captures a target before it is edited (or added), does nothing outside of a gesture or
+    /// transaction. The target must not be locked by the caller.
+    pub fn history_touch_target(&self, id: &TargetId) {
+        if self
+            .history
+            .read()
+            .pending()
+            .is_none_or(|step| step.target_touched(id))
+        {
+            return;
+        }
+        let target = self
+            .sheet_get_comm_target(id)
+            .map(|target| target.read().clone());
+        if let Some(step) = self.history.write().pending_mut() {
+            step.touch_target(id.clone(), target);
+        }
+    }
+    pub fn history_undo(&self) {
+        self.history_settle_gesture();
+        let step = self.history.write().pop_undo();
+        if let Some(step) = step {
+            self.history_apply(&step, false);
+        }
+    }
+    pub fn history_redo(&self) {
+        self.history_settle_gesture();
+        let step = self.history.write().pop_redo();
+        if let Some(step) = step {
+            self.history_apply(&step, true);
+        }
+    }
+    pub fn history_clear(&self) {
+        self.history.write().clear();
+    }
+    fn history_apply(&self, step: &HistoryStep, forward: bool) {
+        fn restore<K, V>(map: &DashMap<K, Arc<RwLock<V>>>, id: &K, value: &Option<Box<V>>)
+        where
+            K: Clone + Eq + Hash,
+            V: Clone,
+        {
+            match value {
+                Some(value) => match map.get(id).map(|item| item.clone()) {
+                    Some(item) => *item.write() = V::clone(value),
+                    None => {
+                        map.insert(id.clone(), Arc::new(RwLock::new(V::clone(value))));
+                    }
+                },
+                None => {
+                    map.remove(id);
+                }
+            }
+        }
+
+        for command in step.commands.iter() {
+            match command {
+                HistoryCommand::Props { before, after } => {
+                    let props = if forward { after } else { before };
+                    *self.sheet.bpm.write() = props.bpm;
+                    *self.sheet.ticks_per_beat.write() = props.ticks_per_beat;
+                    *self.sheet.length_in_beats.write() = props.length_in_beats;
+                    *self.sheet.tracks_ordering.write() = props.tracks_ordering.clone();
+                    *self.sheet.patterns_ordering.write() = props.patterns_ordering.clone();
+                    *self.sheet.targets_ordering.write() = props.targets_ordering.clone();
+                }
+                HistoryCommand::Track { id, before, after } => {
+                    restore(&self.sheet.tracks, id, if forward { after } else { before });
+                }
+                HistoryCommand::Pattern { id, before, after } => {
+                    restore(
+                        &self.sheet.patterns,
+                        id,
+                        if forward { after } else { before },
+                    );
+                }
+                HistoryCommand::Target { id, before, after } => {
+                    restore(
+                        &self.sheet.targets,
+                        id,
+                        if forward { after } else { before },
+                    );
+                }
+            }
+        }
+    }
+    fn history_close(&self, step: Option<PendingStep>) {
+        let Some(step) = step else {
+            return;
+        };
+        let commands = step.into_commands(
+            || self.sheet_props(),
+            |id| self.sheet_get_track(id).map(|track| track.read().clone()),
+            |id| self.sheet_get_pattern(id).map(|pat| pat.read().clone()),
+            |id| {
+                self.sheet_get_comm_target(id)
+                    .map(|target| target.read().clone())
+            },
+        );
+        self.history.write().push(commands);
+    }
+    fn sheet_props(&self) -> SheetProps {
+        SheetProps {
+            bpm: *self.sheet.bpm.read(),
+            ticks_per_beat: *self.sheet.ticks_per_beat.read(),
+            length_in_beats: *self.sheet.length_in_beats.read(),
+            tracks_ordering: self.sheet.tracks_ordering.read().clone(),
+            patterns_ordering: self.sheet.patterns_ordering.read().clone(),
+            targets_ordering: self.sheet.targets_ordering.read().clone(),
+        }
     }
 }
 
@@ -434,17 +1020,67 @@ impl CentralState {
     /// Returns the tick limit for metronome.
     pub fn metro_tick_limit(&self) -> u64 {
         match *self.app.player_context.read() {
-            PlayerContext::Sheet => self.sheet.length_in_beats.read().get() * TICK_PER_BEAT - 1,
+            PlayerContext::Sheet => {
+                self.sheet.length_in_beats.read().get() * self.sheet_ticks_per_beat() - 1
+            }
             PlayerContext::Pattern => self
                 .selected_pattern()
                 .as_ref()
-                .map(|pat| pat.item.read().beats() * TICK_PER_BEAT - 1)
+                .map(|pat| pat.item.read().beats() * self.sheet_ticks_per_beat() - 1)
                 .unwrap(),
         }
     }
+    /// Returns the BPM the metronome should currently run at.
+    pub fn metro_bpm(&self) -> f64 {
+        if let Some(bpm) = self.metro_bpm_override() {
+            return bpm;
+        }
+        match *self.app.player_context.read() {
+            PlayerContext::Sheet => self.sheet_bpm_at(*self.metro.curr_tick.read()),
+            PlayerContext::Pattern => self.sheet_bpm(),
+        }
+    }
+    /// Returns how long a tick currently lasts.
+    pub fn metro_tick_interval(&self) -> Duration {
+        Duration::from_secs_f64(60. / (self.metro_bpm() * self.sheet_ticks_per_beat() as f64))
+    }
     pub fn metro_playing(&self) -> bool {
         *self.metro.playing.read()
     }
+    pub fn metro_looping(&self) -> bool {
+        *self.metro.looping.read()
+    }
+    pub fn metro_set_looping(&self, looping: bool) {
+        *self.metro.looping.write() = looping;
+    }
+    pub fn metro_bpm_override(&self) -> Option<f64> {
+        *self.metro.bpm_override.read()
+    }
+    pub fn metro_override_bpm(&self, bpm: Option<f64>) {
+        *self.metro.bpm_override.write() = bpm;
+    }
+    /// Returns how precisely the metronome has been ticking since playback started.
+    pub fn metro_jitter(&self) -> TickJitter {
+        *self.metro.jitter.read()
+    }
+    pub fn metro_record_jitter(&self, lateness: Duration) {
+        self.metro.jitter.write().record(lateness);
+    }
+    /// Returns the ticks the routines fell behind on since playback started, in total.
+    pub fn metro_lagged_ticks(&self) -> u64 {
+        self.metro
+            .lagged_ticks
+            .iter()
+            .map(|entry| *entry.value())
+            .sum()
+    }
+    pub fn metro_report_lag(&self, id: RoutineId) {
+        *self.metro.lagged_ticks.entry(id).or_default() += 1;
+    }
+    pub fn metro_reset_stats(&self) {
+        *self.metro.jitter.write() = TickJitter::default();
+        self.metro.lagged_ticks.clear();
+    }
 
     pub fn metro_stopped(&self) -> bool {
         *self.metro.curr_tick.read() == 0 && !*self.metro.playing.read()
@@ -453,36 +1089,71 @@ impl CentralState {
     pub fn metro_toggle_playing(&self, value: Option<bool>) {
         let mut playing = self.metro.playing.write();
         *playing = value.unwrap_or(!*playing);
+        if *playing {
+            *self.metro.finished.write() = false;
+        }
+    }
+    /// Stops playback at the end of a non-looping sheet.
+    pub fn metro_finish(&self) {
+        *self.metro.playing.write() = false;
+        *self.metro.finished.write() = true;
+    }
+    pub fn metro_finished(&self) -> bool {
+        *self.metro.finished.read()
     }
 
     pub fn metro_make_stop(&self) {
         *self.metro.playing.write() = false;
-        *self.metro.curr_tick.write() = 0;
-        self.metro.tick_memory.clear();
+        self.metro_seek(0);
+    }
+    /// Moves playback to the given tick, played right away if playing or once playback starts.
+    pub fn metro_seek(&self, tick: u64) {
+        // the tick stays locked, so the metronome never advances past an undelivered seek
+        let mut curr_tick = self.metro.curr_tick.write();
+        *curr_tick = tick;
+        *self.metro.tick_delivered.write() = false;
+    }
+    pub fn metro_tick_delivered(&self) -> bool {
+        *self.metro.tick_delivered.read()
+    }
+    pub fn metro_set_tick_delivered(&self, delivered: bool) {
+        *self.metro.tick_delivered.write() = delivered;
     }
 
+    pub fn metro_tick(&self) -> u64 {
+        *self.metro.curr_tick.read()
+    }
     pub fn metro_tick_mut(&self) -> RwLockWriteGuard<'_, u64> {
         self.metro.curr_tick.write()
     }
 
-    /// Requests the current tick for the given routine.
+    /// Subscribes the routine to every tick of the metronome, in playing order.
     ///
-    /// A routine may only receive a tick once, any subsequent requests within the same
-    /// tick will return `None`. If the metronome is not playing, `None` is returned.
-    /// To get the current tick without context and restrictions, use `.query_tick()`.
-    pub fn metro_request_tick(&self, id: RoutineId) -> Option<u64> {
-        if !*self.metro.playing.read() {
-            return None;
-        }
-
-        let curr_tick = self.metro.curr_tick.read();
-        if let Some(last_tick) = self.metro.tick_memory.get(&id)
-            && *last_tick == *curr_tick
-        {
-            None
-        } else {
-            self.metro.tick_memory.insert(id, *curr_tick);
-            Some(*curr_tick)
-        }
+    /// Subscribing again replaces the previous subscription of the routine.
+    pub fn metro_subscribe(&self, id: RoutineId) -> mpsc::Receiver<MetroEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.metro.subscribers.insert(id, tx);
+        rx
+    }
+    /// Pushes the event to every subscribed routine, dropping those no longer listening.
+    pub fn metro_broadcast(&self, event: MetroEvent) {
+        self.metro
+            .subscribers
+            .retain(|_, tx| tx.send(event).is_ok());
     }
 }
+
+// LYN: Helpers
+
+/// How long reading a WebSocket for pongs may block.
+const PONG_READ_TIMEOUT: Duration = Duration::from_millis(1);
+/// Idle time before TCP keepalive probes start, and the time in between them.
+const KEEPALIVE_TIME: Duration = Duration::from_secs(5);
+
+/// Has the OS probe the connection while idle, so a vanished peer fails the next write.
+fn set_keepalive(stream: &TcpStream) -> io::Result<()> {
+    let keepalive = TcpKeepalive::new()
+        .with_time(KEEPALIVE_TIME)
+        .with_interval(KEEPALIVE_TIME);
+    SockRef::from(stream).set_tcp_keepalive(&keepalive)
+}
@@ -1,8 +1,12 @@
-use std::sync::Arc;
+use std::{fmt::Display, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 
-use crate::model::{comm::SheetMessage, state::CentralState, track::pattern::PatternTrack};
+use crate::model::{
+    comm::SheetMessage,
+    state::CentralState,
+    track::{pattern::PatternTrack, timeline::TimelineTrack},
+};
 
 pub mod pattern;
 pub mod timeline;
@@ -12,13 +16,38 @@ pub mod timeline;
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SheetTrackType {
     Pattern,
-    // Timeline,
+    Timeline,
+}
+
+impl Display for SheetTrackType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SheetTrackType::Pattern => write!(f, "片段轨道"),
+            SheetTrackType::Timeline => write!(f, "时间轴轨道"),
+        }
+    }
+}
+
+impl SheetTrackType {
+    pub fn variants() -> &'static [SheetTrackType] {
+        &[SheetTrackType::Pattern, SheetTrackType::Timeline]
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SheetTrack {
     Pattern(PatternTrack),
-    // Timeline(TimelineTrack),
+    Timeline(TimelineTrack),
+}
+
+impl SheetTrack {
+    /// Converts all ticks of the track from one resolution to another.
+    pub fn rescale_ticks(&mut self, from: u64, to: u64) {
+        match self {
+            Self::Pattern(track) => track.rescale_ticks(from, to),
+            Self::Timeline(track) => track.rescale_ticks(from, to),
+        }
+    }
 }
 
 pub trait SheetTrackTrait {
@@ -32,6 +61,10 @@ pub trait SheetTrackTrait {
     fn color_mut(&mut self) -> &mut ecolor::Color32;
 
     fn msg_at(&self, tick: u64, state: Arc<CentralState>) -> Vec<SheetMessage>;
+    /// Messages releasing whatever is still sounding when playback stops at the given tick.
+    fn release_at(&self, _tick: u64, _state: Arc<CentralState>) -> Vec<SheetMessage> {
+        Vec::new()
+    }
 }
 
 impl SheetTrackTrait for SheetTrack {
@@ -39,12 +72,14 @@ impl SheetTrackTrait for SheetTrack {
     fn name_ref(&self) -> &String {
         match self {
             Self::Pattern(track) => track.name_ref(),
+            Self::Timeline(track) => track.name_ref(),
         }
     }
     #[inline]
     fn name_mut(&mut self) -> &mut String {
         match self {
             Self::Pattern(track) => track.name_mut(),
+            Self::Timeline(track) => track.name_mut(),
         }
     }
 
@@ -52,12 +87,14 @@ impl SheetTrackTrait for SheetTrack {
     fn icon_ref(&self) -> &String {
         match self {
             Self::Pattern(track) => track.icon_ref(),
+            Self::Timeline(track) => track.icon_ref(),
         }
     }
     #[inline]
     fn icon_mut(&mut self) -> &mut String {
         match self {
             Self::Pattern(track) => track.icon_mut(),
+            Self::Timeline(track) => track.icon_mut(),
         }
     }
 
@@ -65,12 +102,14 @@ impl SheetTrackTrait for SheetTrack {
     fn color(&self) -> ecolor::Color32 {
         match self {
             Self::Pattern(track) => track.color(),
+            Self::Timeline(track) => track.color(),
         }
     }
     #[inline]
     fn color_mut(&mut self) -> &mut ecolor::Color32 {
         match self {
             Self::Pattern(track) => track.color_mut(),
+            Self::Timeline(track) => track.color_mut(),
         }
     }
 
@@ -78,6 +117,14 @@ impl SheetTrackTrait for SheetTrack {
     fn msg_at(&self, tick: u64, state: Arc<CentralState>) -> Vec<SheetMessage> {
         match self {
             Self::Pattern(track) => track.msg_at(tick, state),
+            Self::Timeline(track) => track.msg_at(tick, state),
+        }
+    }
+    #[inline]
+    fn release_at(&self, tick: u64, state: Arc<CentralState>) -> Vec<SheetMessage> {
+        match self {
+            Self::Pattern(track) => track.release_at(tick, state),
+            Self::Timeline(track) => track.release_at(tick, state),
         }
     }
 }
@@ -4,15 +4,13 @@ use interavl::IntervalTree;
 use lyn_util::egui::LynId;
 use serde::{Deserialize, Serialize, ser::SerializeStruct};
 
-use crate::{
-    model::{
-        DEFAULT_ICON, DEFAULT_PATTERN_NAME, DEFAULT_SELECTABLE_COLOR,
-        comm::SheetMessage,
-        pattern::SheetPatternTrait,
-        state::{CentralState, PatternId},
-        track::SheetTrackTrait,
-    },
-    routines::metronome::TICK_PER_BEAT,
+use crate::model::{
+    DEFAULT_ICON, DEFAULT_PATTERN_NAME, DEFAULT_SELECTABLE_COLOR,
+    comm::SheetMessage,
+    pattern::SheetPatternTrait,
+    rescale_tick,
+    state::{CentralState, PatternId},
+    track::SheetTrackTrait,
 };
 
 #[derive(Debug, Clone)]
@@ -77,12 +75,27 @@ impl PatternTrack {
         }
     }
 
-    pub fn beats(&self) -> u64 {
+    pub fn beats(&self, ticks_per_beat: u64) -> u64 {
         let last_tick = self.patterns.max_interval_end().map_or(0, |end| *end);
-        if last_tick.is_multiple_of(TICK_PER_BEAT) {
+        if last_tick.is_multiple_of(ticks_per_beat) {
             last_tick
         } else {
-            last_tick + (TICK_PER_BEAT - last_tick % TICK_PER_BEAT)
+            last_tick + (ticks_per_beat - last_tick % ticks_per_beat)
+        }
+    }
+    pub fn rescale_ticks(&mut self, from: u64, to: u64) {
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|(range, vec)| (range.clone(), vec.clone()))
+            .collect::<Vec<_>>();
+        self.patterns = IntervalTree::default();
+        for (range, vec) in patterns {
+            let start = rescale_tick(range.start, from, to);
+            let end = rescale_tick(range.end, from, to).max(start + 1);
+            for (ui_id, pattern_id) in vec {
+                self.add_pattern_inner(start..end, pattern_id, Some(ui_id));
+            }
         }
     }
 }
@@ -115,12 +128,48 @@ impl SheetTrackTrait for PatternTrack {
     #[inline]
     fn msg_at(&self, tick: u64, state: Arc<CentralState>) -> Vec<SheetMessage> {
         let mut msgs = Vec::new();
-        for (range, vec) in self.patterns.iter_overlaps(&(tick..tick + 1)) {
+        let ticks_per_beat = state.sheet_ticks_per_beat();
+        // ranges ending right at this tick still need to release their notes
+        for (range, vec) in self
+            .patterns
+            .iter_overlaps(&(tick.saturating_sub(1)..tick + 1))
+        {
+            if tick < range.start {
+                continue;
+            }
+            for (_, pat_id) in vec {
+                if let Some(pattern) = state.sheet_get_pattern(pat_id) {
+                    let pattern = pattern.read();
+                    let pattern_tick = tick - range.start;
+                    // the range may be stretched beyond or cut shorter than the pattern itself
+                    let cut_tick = (pattern.beats() * ticks_per_beat).min(range.end - range.start);
+                    if pattern_tick < cut_tick {
+                        msgs.append(&mut pattern.msg_at(pattern_tick));
+                    } else if pattern_tick == cut_tick {
+                        msgs.append(&mut pattern.release_at(pattern_tick));
+                    }
+                }
+            }
+        }
+        msgs
+    }
+    #[inline]
+    fn release_at(&self, tick: u64, state: Arc<CentralState>) -> Vec<SheetMessage> {
+        let mut msgs = Vec::new();
+        if tick == 0 {
+            return msgs;
+        }
+        let ticks_per_beat = state.sheet_ticks_per_beat();
+        for (range, vec) in self.patterns.iter_overlaps(&(tick.saturating_sub(1)..tick)) {
             for (_, pat_id) in vec {
                 if let Some(pattern) = state.sheet_get_pattern(pat_id) {
                     let pattern = pattern.read();
                     let pattern_tick = tick - range.start;
-                    msgs.append(&mut pattern.msg_at(pattern_tick));
+                    let cut_tick = (pattern.beats() * ticks_per_beat).min(range.end - range.start);
+                    // patterns already past their end have released everything
+                    if pattern_tick <= cut_tick {
+                        msgs.append(&mut pattern.release_at(pattern_tick));
+                    }
                 }
             }
         }
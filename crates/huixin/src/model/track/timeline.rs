@@ -1,5 +1,182 @@
-#[derive(Debug)]
-pub struct EventTrack {
+use std::{collections::BTreeMap, fmt::Display, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::{
+    DEFAULT_ICON, DEFAULT_SELECTABLE_COLOR, DEFAULT_TRACK_NAME, comm::SheetMessage, rescale_tick,
+    state::CentralState, track::SheetTrackTrait,
+};
+
+pub const DEFAULT_MARKER_NAME: &str = "标记";
+
+// LYN: Timeline Track
+
+/// A track carrying the tempo map, time signatures and section markers of the sheet.
+///
+/// Every entry takes effect from its tick until the next entry of the same kind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineTrack {
     pub name: String,
-    // event vec, target
+    pub icon: String,
+    pub color: ecolor::Color32,
+
+    tempos: BTreeMap<u64, f64>,
+    signatures: BTreeMap<u64, TimeSignature>,
+    markers: BTreeMap<u64, String>,
+}
+
+impl TimelineTrack {
+    pub fn new() -> Self {
+        Self {
+            name: String::from(DEFAULT_TRACK_NAME),
+            icon: String::from(DEFAULT_ICON),
+            color: DEFAULT_SELECTABLE_COLOR,
+            tempos: BTreeMap::new(),
+            signatures: BTreeMap::new(),
+            markers: BTreeMap::new(),
+        }
+    }
+
+    pub fn tempos_iter(&self) -> impl Iterator<Item = (&u64, &f64)> {
+        self.tempos.iter()
+    }
+    pub fn set_tempo(&mut self, tick: u64, bpm: f64) {
+        self.tempos.insert(tick, bpm);
+    }
+    pub fn del_tempo(&mut self, tick: u64) -> Option<f64> {
+        self.tempos.remove(&tick)
+    }
+    /// Returns the BPM in effect at the given tick, `None` if no tempo change precedes it.
+    pub fn bpm_at(&self, tick: u64) -> Option<f64> {
+        self.tempos.range(..=tick).next_back().map(|(_, bpm)| *bpm)
+    }
+
+    pub fn signatures_iter(&self) -> impl Iterator<Item = (&u64, &TimeSignature)> {
+        self.signatures.iter()
+    }
+    pub fn set_signature(&mut self, tick: u64, signature: TimeSignature) {
+        self.signatures.insert(tick, signature);
+    }
+    pub fn del_signature(&mut self, tick: u64) -> Option<TimeSignature> {
+        self.signatures.remove(&tick)
+    }
+    /// Returns the time signature in effect at the given tick, defaults to 4/4.
+    pub fn signature_at(&self, tick: u64) -> TimeSignature {
+        self.signatures
+            .range(..=tick)
+            .next_back()
+            .map(|(_, sig)| *sig)
+            .unwrap_or_default()
+    }
+
+    pub fn markers_iter(&self) -> impl Iterator<Item = (&u64, &String)> {
+        self.markers.iter()
+    }
+    pub fn set_marker(&mut self, tick: u64, name: String) {
+        self.markers.insert(tick, name);
+    }
+    pub fn del_marker(&mut self, tick: u64) -> Option<String> {
+        self.markers.remove(&tick)
+    }
+
+    /// Returns the starting tick of every bar up to (and including) `until`.
+    ///
+    /// A time signature change always starts a new bar, even if it lands in the middle of one.
+    pub fn bar_ticks(&self, until: u64, ticks_per_beat: u64) -> Vec<u64> {
+        let mut bars = Vec::new();
+        let mut changes = self.signatures.iter().peekable();
+        let mut signature = TimeSignature::default();
+        let mut tick = 0;
+        while tick <= until {
+            while let Some(&(&at, next)) = changes.peek()
+                && at <= tick
+            {
+                signature = *next;
+                changes.next();
+            }
+            bars.push(tick);
+            let next_bar = tick + signature.ticks_per_bar(ticks_per_beat);
+            tick = match changes.peek() {
+                Some(&(&at, _)) if at < next_bar => at,
+                _ => next_bar,
+            };
+        }
+        bars
+    }
+
+    pub fn rescale_ticks(&mut self, from: u64, to: u64) {
+        fn rescale<T>(map: &mut BTreeMap<u64, T>, from: u64, to: u64) {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(tick, entry)| (rescale_tick(tick, from, to), entry))
+                .collect();
+        }
+        rescale(&mut self.tempos, from, to);
+        rescale(&mut self.signatures, from, to);
+        rescale(&mut self.markers, from, to);
+    }
+}
+
+impl SheetTrackTrait for TimelineTrack {
+    #[inline]
+    fn name_ref(&self) -> &String {
+        &self.name
+    }
+    #[inline]
+    fn name_mut(&mut self) -> &mut String {
+        &mut self.name
+    }
+    #[inline]
+    fn icon_ref(&self) -> &String {
+        &self.icon
+    }
+    #[inline]
+    fn icon_mut(&mut self) -> &mut String {
+        &mut self.icon
+    }
+    #[inline]
+    fn color(&self) -> ecolor::Color32 {
+        self.color
+    }
+    #[inline]
+    fn color_mut(&mut self) -> &mut ecolor::Color32 {
+        &mut self.color
+    }
+    #[inline]
+    fn msg_at(&self, _tick: u64, _state: Arc<CentralState>) -> Vec<SheetMessage> {
+        Vec::new()
+    }
+}
+
+// LYN: Time Signature
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+impl Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl TimeSignature {
+    pub const DENOMINATORS: [u64; 5] = [1, 2, 4, 8, 16];
+
+    /// Length of a bar in ticks, never zero.
+    #[inline]
+    pub fn ticks_per_bar(&self, ticks_per_beat: u64) -> u64 {
+        (self.numerator * ticks_per_beat * 4 / self.denominator.max(1)).max(1)
+    }
 }
@@ -0,0 +1,134 @@
+use std::{collections::VecDeque, io, time::Instant};
+
+use serde::Serialize;
+
+use crate::model::comm::SheetBatch;
+
+// LYN: Traffic Log
+
+/// The most recent instructions sent to targets and connection events, for inspecting.
+#[derive(Debug)]
+pub struct TrafficLog {
+    started: Instant,
+    entries: VecDeque<TrafficEntry>,
+    paused: bool,
+    /// Whether the inspector is open, instructions are only recorded meanwhile as formatting
+    /// them is not free.
+    inspected: bool,
+}
+
+impl Default for TrafficLog {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            entries: VecDeque::new(),
+            paused: false,
+            inspected: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrafficEntry {
+    /// Seconds since the log started.
+    pub time: f64,
+    /// Name of the target at the time.
+    pub target: String,
+    #[serde(flatten)]
+    pub kind: TrafficKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TrafficKind {
+    Instruction {
+        tick: u64,
+        tag: String,
+        /// The data of the instruction as JSON, regardless of the format it was sent in.
+        payload: String,
+        delivered: bool,
+    },
+    Connection {
+        message: String,
+    },
+}
+
+impl TrafficKind {
+    /// One entry per instruction of the batch.
+    pub fn from_batch(batch: &SheetBatch, delivered: bool) -> impl Iterator<Item = Self> + '_ {
+        batch.payloads.iter().map(move |payload| Self::Instruction {
+            tick: batch.tick,
+            tag: payload.tag.clone(),
+            payload: json::to_string(&payload.data).unwrap_or_default(),
+            delivered,
+        })
+    }
+}
+
+impl TrafficEntry {
+    /// Whether any of the shown text contains the filter, ignoring case.
+    pub fn matches(&self, filter: &str) -> bool {
+        if filter.is_empty() {
+            return true;
+        }
+        let filter = filter.to_lowercase();
+        let contains = |text: &str| text.to_lowercase().contains(&filter);
+        contains(&self.target)
+            || match &self.kind {
+                TrafficKind::Instruction { tag, payload, .. } => contains(tag) || contains(payload),
+                TrafficKind::Connection { message } => contains(message),
+            }
+    }
+}
+
+impl TrafficLog {
+    pub const CAPACITY: usize = 2000;
+
+    pub fn record(&mut self, target: String, kind: TrafficKind) {
+        if self.paused {
+            return;
+        }
+        if self.entries.len() >= Self::CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TrafficEntry {
+            time: self.started.elapsed().as_secs_f64(),
+            target,
+            kind,
+        });
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &VecDeque<TrafficEntry> {
+        &self.entries
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+    #[inline]
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    /// Whether sent instructions should be recorded at all.
+    pub fn wants_instructions(&self) -> bool {
+        self.inspected && !self.paused
+    }
+    pub fn set_inspected(&mut self, inspected: bool) {
+        self.inspected = inspected;
+    }
+}
+
+/// Writes the entries as one JSON object per line.
+pub fn write_entries<'a>(
+    entries: impl IntoIterator<Item = &'a TrafficEntry>,
+    mut writer: impl io::Write,
+) -> io::Result<()> {
+    for entry in entries {
+        json::to_writer(&mut writer, entry)?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
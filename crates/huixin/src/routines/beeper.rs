@@ -0,0 +1,175 @@
+use std::{
+    f64::consts::TAU,
+    sync::{Arc, mpsc},
+};
+
+use cpal::{
+    FromSample, I24, SizedSample, U24,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use log::{info, warn};
+use parking_lot::Mutex;
+
+use crate::model::{loopback::unix_now, state::CentralState};
+
+/// Seconds a beep takes to fade out.
+const BEEP_LENGTH: f64 = 0.15;
+const BEEP_VOLUME: f32 = 0.2;
+const MAX_VOICES: usize = 16;
+
+// LYN: Beeper Main Routine
+
+/// Plays a short beep for every note the loopback targets receive, while beeping is on.
+///
+/// The output device is only opened once the first beep comes in.
+pub fn main(state: Arc<CentralState>, beep_rx: mpsc::Receiver<Beep>) -> ! {
+    info!("Beeper started");
+
+    let voices: Arc<Mutex<Vec<Voice>>> = Arc::default();
+    // kept alive for as long as beeps are played
+    let mut output: Option<Option<(cpal::Stream, f64)>> = None;
+    loop {
+        let beep = beep_rx.recv().expect("Beep channel unexpectedly closed");
+        let output = output.get_or_insert_with(|| match open_output(voices.clone()) {
+            Ok(output) => Some(output),
+            Err(e) => {
+                warn!("Failed to open audio output for beeping: {e}");
+                state.app_set_notice_msg(Some(format!("无法打开音频输出：{e}")));
+                None
+            }
+        });
+        let Some((_, sample_rate)) = output else {
+            continue;
+        };
+
+        let mut voices = voices.lock();
+        if voices.len() >= MAX_VOICES {
+            voices.remove(0);
+        }
+        voices.push(Voice::new(beep, *sample_rate));
+    }
+}
+
+// LYN: Beep
+
+#[derive(Debug, Clone, Copy)]
+pub struct Beep {
+    pub midicode: u8,
+    /// From `0` to `1`.
+    pub strength: f32,
+    /// When to beep, in seconds since the UNIX epoch.
+    pub timestamp: f64,
+}
+
+/// A decaying sine wave at the pitch of a note.
+#[derive(Debug)]
+struct Voice {
+    /// Cycles per sample.
+    step: f64,
+    phase: f64,
+    amplitude: f32,
+    /// Samples to wait before sounding.
+    delay: u64,
+    remaining: u64,
+    length: u64,
+}
+
+impl Voice {
+    fn new(beep: Beep, sample_rate: f64) -> Self {
+        let freq = 440. * 2f64.powf((beep.midicode as f64 - 69.) / 12.);
+        let length = (BEEP_LENGTH * sample_rate) as u64;
+        Self {
+            step: freq / sample_rate,
+            phase: 0.,
+            amplitude: beep.strength * BEEP_VOLUME,
+            delay: ((beep.timestamp - unix_now()).max(0.) * sample_rate) as u64,
+            remaining: length,
+            length: length.max(1),
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        if self.delay > 0 {
+            self.delay -= 1;
+            return 0.;
+        }
+        if self.remaining == 0 {
+            return 0.;
+        }
+        let envelope = self.remaining as f32 / self.length as f32;
+        self.remaining -= 1;
+        let sample = (self.phase * TAU).sin() as f32 * self.amplitude * envelope;
+        self.phase = (self.phase + self.step).fract();
+        sample
+    }
+
+    #[inline]
+    fn finished(&self) -> bool {
+        self.delay == 0 && self.remaining == 0
+    }
+}
+
+// LYN: Audio Output
+
+/// Opens the default output device, returns the playing stream and its sample rate.
+fn open_output(voices: Arc<Mutex<Vec<Voice>>>) -> Result<(cpal::Stream, f64), String> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("no output device available")?;
+    let supported_config = device.default_output_config().map_err(|e| e.to_string())?;
+    let format = supported_config.sample_format();
+    let config = supported_config.config();
+    let sample_rate = config.sample_rate as f64;
+
+    let stream = match format {
+        cpal::SampleFormat::I8 => build_stream::<i8>(&device, config, voices),
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, config, voices),
+        cpal::SampleFormat::I24 => build_stream::<I24>(&device, config, voices),
+        cpal::SampleFormat::I32 => build_stream::<i32>(&device, config, voices),
+        cpal::SampleFormat::I64 => build_stream::<i64>(&device, config, voices),
+
+        cpal::SampleFormat::U8 => build_stream::<u8>(&device, config, voices),
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, config, voices),
+        cpal::SampleFormat::U24 => build_stream::<U24>(&device, config, voices),
+        cpal::SampleFormat::U32 => build_stream::<u32>(&device, config, voices),
+        cpal::SampleFormat::U64 => build_stream::<u64>(&device, config, voices),
+
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, config, voices),
+        cpal::SampleFormat::F64 => build_stream::<f64>(&device, config, voices),
+
+        sample_format => return Err(format!("unsupported sample format '{sample_format}'")),
+    }
+    .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+    Ok((stream, sample_rate))
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: cpal::StreamConfig,
+    voices: Arc<Mutex<Vec<Voice>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        &config,
+        move |output: &mut [T], _| {
+            let mut voices = voices.lock();
+            for frame in output.chunks_mut(channels) {
+                let sample = voices
+                    .iter_mut()
+                    .map(Voice::next_sample)
+                    .sum::<f32>()
+                    .clamp(-1., 1.);
+                frame.fill(T::from_sample(sample));
+            }
+            voices.retain(|voice| !voice.finished());
+        },
+        move |err| {
+            warn!("Audio output error: {err}");
+        },
+        None,
+    )
+}
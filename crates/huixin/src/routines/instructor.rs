@@ -1,68 +1,262 @@
 use std::{
+    cmp,
     sync::{Arc, mpsc},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use log::{info, warn};
 
-use crate::model::{comm::SheetMessage, state::CentralState};
+use lyn_util::comm::Format;
 
-const NO_MSG_CHECK_HEALTH_INTERVAL: Duration = Duration::from_millis(50);
+use crate::model::{
+    comm::{CommStreamErr, SendQueue, SheetBatch},
+    state::{CentralState, TargetId},
+};
+
+const CHECK_HEALTH_INTERVAL: Duration = Duration::from_millis(50);
+/// Time between heartbeats to a connected target, TCP relies on keepalive instead.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// A heartbeat left unanswered this long counts as a lost connection.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often a heartbeat is checked for its pong, which the latency is measured with.
+const PONG_POLL_INTERVAL: Duration = Duration::from_millis(1);
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
 
 // LYN: Instructor Main Routine
 
-pub fn main(state: Arc<CentralState>, msg_rx: mpsc::Receiver<SheetMessage>) -> ! {
+/// Dispatches batches to the send queue of their target, each drained by its own writer so
+/// messages to one target stay in order and a slow target never holds up the others.
+pub fn main(state: Arc<CentralState>, msg_rx: mpsc::Receiver<SheetBatch>) -> ! {
     info!("Instructor started");
 
+    let mut last_checked: Option<Instant> = None;
     loop {
-        if let Ok(msg) = msg_rx.try_recv() {
-            let Some(entry) = state.sheet_get_comm_target(&msg.target_id) else {
+        match msg_rx.recv_timeout(CHECK_HEALTH_INTERVAL) {
+            Ok(batch) => {
+                if let Some(entry) = state.sheet_get_comm_target(&batch.target_id) {
+                    let policy = entry.read().overflow;
+                    open_queue(&state, &batch.target_id).push(batch, policy);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                panic!("Instruction messaging channel unexpectedly closed")
+            }
+        }
+
+        // every target gets a writer, which keeps it connected
+        if last_checked.is_none_or(|checked| checked.elapsed() >= CHECK_HEALTH_INTERVAL) {
+            state.comm_close_stale_queues();
+            for entry in state.sheet_comm_targets_iter() {
+                open_queue(&state, entry.key());
+            }
+            last_checked = Some(Instant::now());
+        }
+    }
+}
+
+// LYN: Target Writer
+
+/// Sends the batches queued for one target, keeping it connected meanwhile.
+fn writer(state: Arc<CentralState>, id: TargetId, queue: Arc<SendQueue>) {
+    let _retire = RetireOnExit {
+        state: state.clone(),
+        id: id.clone(),
+        queue: queue.clone(),
+    };
+    let mut link = Link::default();
+    while !queue.is_closed() {
+        let wait = if link.ping_sent.is_some() {
+            PONG_POLL_INTERVAL
+        } else {
+            CHECK_HEALTH_INTERVAL
+        };
+        let batch = queue.pop(wait);
+        let Some(entry) = state.sheet_get_comm_target(&id) else {
+            break;
+        };
+        let dest = {
+            let guard = entry.read();
+            (guard.addr.clone(), guard.format)
+        };
+        // a new address or format deserves a fresh attempt
+        if link.dest.as_ref() != Some(&dest) {
+            link = Link {
+                dest: Some(dest.clone()),
+                ..Link::default()
+            };
+        }
+        let (addr, format) = dest;
+
+        if !state.comm_stream_exists(&id) && !link.connect(&state, &id, &addr, format) {
+            if let Some(batch) = batch {
+                queue.report_failed();
+                state.traffic_record_batch(&batch, false);
+            }
+            continue;
+        }
+        if let Some(batch) = batch {
+            let count = batch.payloads.len() as u64;
+            let Some(data) = batch.form_bytes(format) else {
+                warn!("Failed to serialize instruction payload for {addr}, dropping it");
+                queue.report_failed();
+                state.traffic_record_batch(&batch, false);
                 continue;
             };
-            let entry = entry.clone();
-            state.worker_spawn_task({
-                let state = state.clone();
-                move || {
-                    let (id, addr, format) = {
-                        let guard = entry.read();
-                        (msg.target_id.clone(), guard.addr.clone(), guard.format)
-                    };
-                    if state.comm_get_stream(&id).is_none() {
-                        state.comm_connect_stream_blocking(id, &addr, format);
-                        return;
-                    }
-                    let data = msg
-                        .payload
-                        .form_string(format)
-                        .expect("Failed to serialize instruction payload")
-                        .into();
-                    if let Err(err) = state.comm_send_data_blocking(&id, data) {
-                        warn!("Failed to send insturction payload to {}: {err}", addr);
-                        state.comm_connect_stream_blocking(id, &addr, format);
-                    }
+            let len = data.len() as u64;
+            match state.comm_send_data_blocking(&id, data) {
+                Ok(()) => {
+                    queue.report_sent();
+                    state.traffic_record_batch(&batch, true);
+                    state.comm_update_health(&id, |health| {
+                        health.msgs_sent += count;
+                        health.bytes_sent += len;
+                    });
                 }
-            });
-            continue;
+                Err(err) => {
+                    warn!("Failed to send insturction payload to {}: {err}", addr);
+                    queue.report_failed();
+                    state.traffic_record_batch(&batch, false);
+                    link.lost(&state, &id, err);
+                    continue;
+                }
+            }
+        }
+        if let Err(err) = link.heartbeat(&state, &id) {
+            warn!("Lost connection to {addr}: {err}");
+            link.lost(&state, &id, err);
         }
+    }
+}
 
-        // check target health
-        for entry in state.sheet_comm_targets_iter() {
-            let id = entry.key().clone();
-            if state.comm_get_stream(&id).is_none() {
-                let target_entry = entry.clone();
-                state.worker_spawn_task({
-                    let state = state.clone();
-                    move || {
-                        let (addr, format) = {
-                            let guard = target_entry.read();
-                            (guard.addr.clone(), guard.format)
-                        };
-                        state.comm_connect_stream_blocking(id, &addr, format);
-                    }
+/// Closes the queue of a writer once it exits (or panics), so the next batch for the target
+/// spawns a new writer instead of piling up unread.
+#[derive(Debug)]
+struct RetireOnExit {
+    state: Arc<CentralState>,
+    id: TargetId,
+    queue: Arc<SendQueue>,
+}
+
+impl Drop for RetireOnExit {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            warn!(
+                "Writer of target {:?} panicked, it is respawned on demand",
+                self.id
+            );
+        }
+        self.queue.close();
+        self.state.comm_remove_queue(&self.id, &self.queue);
+    }
+}
+
+/// Connection bookkeeping of a writer.
+#[derive(Debug)]
+struct Link {
+    /// The address and format of the target when last checked.
+    dest: Option<(String, Format)>,
+    /// Delay before the next attempt after a failed one, doubling each time.
+    backoff: Duration,
+    retry_at: Option<Instant>,
+    last_ping: Option<Instant>,
+    /// When the heartbeat still waiting for its pong was sent.
+    ping_sent: Option<Instant>,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            dest: None,
+            backoff: MIN_BACKOFF,
+            retry_at: None,
+            last_ping: None,
+            ping_sent: None,
+        }
+    }
+}
+
+impl Link {
+    /// Connects unless still backing off, returns whether connected.
+    fn connect(&mut self, state: &CentralState, id: &TargetId, addr: &str, format: Format) -> bool {
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return false;
+        }
+        match state.comm_connect_stream_blocking(id.clone(), addr, format) {
+            Ok(()) => {
+                info!("Connected to {addr}");
+                state.traffic_record_event(id, format!("已连接到 {addr}"));
+                self.backoff = MIN_BACKOFF;
+                self.retry_at = None;
+                self.last_ping = None;
+                self.ping_sent = None;
+                state.comm_update_health(id, |health| {
+                    health.connected_since = Some(Instant::now());
+                    health.latency = None;
+                    health.retry_at = None;
                 });
+                true
+            }
+            Err(CommStreamErr::Connecting) => false,
+            Err(err) => {
+                state.traffic_record_event(id, format!("无法连接到 {addr}：{err}"));
+                let retry_at = Instant::now() + self.backoff;
+                self.retry_at = Some(retry_at);
+                self.backoff = cmp::min(self.backoff * 2, MAX_BACKOFF);
+                state.comm_update_health(id, |health| {
+                    health.last_error = Some(err.to_string());
+                    health.retry_at = Some(retry_at);
+                });
+                false
+            }
+        }
+    }
+
+    /// Drops the stream, reconnecting is attempted right away.
+    fn lost(&mut self, state: &CentralState, id: &TargetId, err: CommStreamErr) {
+        state.comm_drop_stream(id);
+        state.traffic_record_event(id, format!("连接中断：{err}"));
+        self.ping_sent = None;
+        state.comm_update_health(id, |health| {
+            health.connected_since = None;
+            health.latency = None;
+            health.last_error = Some(err.to_string());
+        });
+    }
+
+    fn heartbeat(&mut self, state: &CentralState, id: &TargetId) -> Result<(), CommStreamErr> {
+        let now = Instant::now();
+        if let Some(sent) = self.ping_sent {
+            if state.comm_poll_pong(id)? {
+                self.ping_sent = None;
+                state.comm_update_health(id, |health| health.latency = Some(sent.elapsed()));
+            } else if now.duration_since(sent) > HEARTBEAT_TIMEOUT {
+                return Err(CommStreamErr::HeartbeatTimeout);
+            }
+        } else if self
+            .last_ping
+            .is_none_or(|at| now.duration_since(at) >= HEARTBEAT_INTERVAL)
+        {
+            self.last_ping = Some(now);
+            if state.comm_send_ping(id)? {
+                self.ping_sent = Some(now);
             }
         }
-        thread::sleep(NO_MSG_CHECK_HEALTH_INTERVAL);
+        Ok(())
+    }
+}
+
+// LYN: Helpers
+
+fn open_queue(state: &Arc<CentralState>, id: &TargetId) -> Arc<SendQueue> {
+    let (queue, created) = state.comm_open_queue(id);
+    if created {
+        thread::spawn({
+            let (state, id, queue) = (state.clone(), id.clone(), queue.clone());
+            move || writer(state, id, queue)
+        });
     }
+    queue
 }
@@ -1,63 +1,197 @@
-use std::{cmp, sync::Arc, thread, time::Duration};
+use std::{
+    cmp, hint,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
-use log::{info, trace};
+use log::info;
 
 use crate::model::state::CentralState;
 
-pub const TICK_PER_BEAT: u64 = 4;
-pub const SLEEP_PER_TICK: u32 = 50;
-pub const MAX_SLEEP_TIME: Duration = Duration::from_millis(50);
+/// Longest single sleep, so tempo changes and pauses are picked up while waiting for a tick.
+pub const MAX_SLEEP_TIME: Duration = Duration::from_millis(5);
+/// The last stretch before a tick is busy-waited, sleeping is not precise enough for it.
+pub const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+/// Falling further behind than this (e.g. system suspended) restarts the clock instead of
+/// rushing through every missed tick.
+pub const MAX_LAG: Duration = Duration::from_millis(250);
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(1);
 
 // LYN: Metronome Main Routine
 
 pub fn main(state: Arc<CentralState>) -> ! {
     info!("Metronome started");
-    let bpm = state.sheet_bpm();
-    let (mut interval, mut sleep_time) = bpm_to_tickable(bpm);
-    let mut remaining = interval;
-    let mut active_bpm = bpm;
+    let mut clock: Option<TickClock> = None;
 
     loop {
         // handle pause / play
-        while !state.metro_playing() {
-            thread::sleep(sleep_time);
+        if !state.metro_playing() {
+            if clock.take().is_some() {
+                state.metro_broadcast(MetroEvent::Stopped);
+            }
+            thread::sleep(IDLE_POLL_INTERVAL);
+            continue;
         }
 
-        // handle bpm change
-        let state_bpm_guard = state.sheet_bpm();
-        if active_bpm != state_bpm_guard {
-            active_bpm = state_bpm_guard;
-            (interval, sleep_time) = bpm_to_tickable(state_bpm_guard);
-            remaining = cmp::min(remaining, interval);
+        // handle bpm / resolution change, the tick in progress is rescheduled right away
+        let interval = tick_interval(state.metro_bpm(), state.sheet_ticks_per_beat());
+        let clock = clock.get_or_insert_with(|| {
+            state.metro_reset_stats();
+            TickClock::start(Instant::now(), interval)
+        });
+        clock.retempo(interval);
+
+        // a fresh start or a seek plays the current tick right away,
+        // resuming a pause continues after the tick already read
+        if !state.metro_tick_delivered() {
+            let now = Instant::now();
+            state.metro_broadcast(MetroEvent::Tick {
+                tick: state.metro_tick(),
+                at: now,
+            });
+            state.metro_set_tick_delivered(true);
+            clock.restart(now);
+            continue;
         }
 
-        // sleep to next tick
-        if remaining > sleep_time {
-            thread::sleep(sleep_time);
-            remaining -= sleep_time;
+        // wait for the next tick
+        let deadline = clock.next_deadline();
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining > SPIN_THRESHOLD {
+            thread::sleep(cmp::min(remaining - SPIN_THRESHOLD, MAX_SLEEP_TIME));
             continue;
         }
-        thread::sleep(remaining);
-        remaining = interval;
+        while Instant::now() < deadline {
+            hint::spin_loop();
+        }
+
+        let now = Instant::now();
+        let lateness = now.duration_since(deadline);
+        if lateness > MAX_LAG {
+            clock.restart(now);
+        } else {
+            clock.advance();
+        }
+        state.metro_record_jitter(lateness);
 
         // update tick
-        {
+        let tick = {
             let limit = state.metro_tick_limit();
             let mut curr_tick_guard = state.metro_tick_mut();
+            // sought meanwhile, the sought tick is played first
+            if !state.metro_tick_delivered() {
+                continue;
+            }
             match limit {
+                // the end of a non-looping sheet rewinds, so playing again starts over
+                top_tick if *curr_tick_guard >= top_tick && !state.metro_looping() => {
+                    *curr_tick_guard = 0;
+                    state.metro_set_tick_delivered(false);
+                    state.metro_finish();
+                    continue;
+                }
                 top_tick if *curr_tick_guard >= top_tick => *curr_tick_guard = 0,
                 _ => *curr_tick_guard = curr_tick_guard.saturating_add(1),
             }
+            *curr_tick_guard
+        };
+        state.metro_broadcast(MetroEvent::Tick { tick, at: deadline });
+    }
+}
+
+// LYN: Metronome Event
+
+/// Pushed to every routine subscribed to the metronome, in order.
+#[derive(Debug, Clone, Copy)]
+pub enum MetroEvent {
+    /// The metronome reached the tick, `at` is when it was due.
+    Tick { tick: u64, at: Instant },
+    /// Playback paused or stopped, no tick follows the last one until it resumes.
+    Stopped,
+}
+
+// LYN: Tick Clock
+
+/// Schedules ticks at absolute deadlines, so oversleeping one tick never delays the next ones.
+///
+/// Deadlines are computed from the instant the current tempo took effect, restarted whenever
+/// the tempo changes.
+#[derive(Debug, Clone, Copy)]
+struct TickClock {
+    anchor: Instant,
+    /// Seconds per tick.
+    interval: f64,
+    /// Ticks passed since `anchor`.
+    ticks: u64,
+}
+
+impl TickClock {
+    fn start(now: Instant, interval: f64) -> Self {
+        Self {
+            anchor: now,
+            interval,
+            ticks: 0,
+        }
+    }
+
+    #[inline]
+    fn deadline(&self, ticks: u64) -> Instant {
+        self.anchor + Duration::from_secs_f64(ticks as f64 * self.interval)
+    }
+    #[inline]
+    fn next_deadline(&self) -> Instant {
+        self.deadline(self.ticks + 1)
+    }
+
+    fn advance(&mut self) {
+        self.ticks += 1;
+    }
+    fn restart(&mut self, now: Instant) {
+        self.anchor = now;
+        self.ticks = 0;
+    }
+    /// Moves the anchor to the last tick, the next one then follows the new tempo.
+    fn retempo(&mut self, interval: f64) {
+        if self.interval == interval {
+            return;
+        }
+        self.anchor = self.deadline(self.ticks);
+        self.ticks = 0;
+        self.interval = interval;
+    }
+}
+
+// LYN: Jitter Statistics
+
+/// How late ticks fired compared to their deadlines, since playback last started.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TickJitter {
+    pub samples: u64,
+    pub last: Duration,
+    pub max: Duration,
+    total: Duration,
+}
+
+impl TickJitter {
+    pub fn record(&mut self, lateness: Duration) {
+        self.samples += 1;
+        self.last = lateness;
+        self.max = cmp::max(self.max, lateness);
+        self.total += lateness;
+    }
+    pub fn mean(&self) -> Duration {
+        match self.samples {
+            0 => Duration::ZERO,
+            samples => self.total.div_f64(samples as f64),
         }
     }
 }
 
 // LYN: Helpers
 
-fn bpm_to_tickable(bpm: f64) -> (Duration, Duration) {
-    let interval = Duration::from_secs_f64(60. / (bpm * TICK_PER_BEAT as f64));
-    (
-        interval,
-        cmp::min(MAX_SLEEP_TIME, interval / SLEEP_PER_TICK),
-    )
+/// Returns the seconds per tick.
+#[inline]
+fn tick_interval(bpm: f64, ticks_per_beat: u64) -> f64 {
+    60. / (bpm * ticks_per_beat as f64)
 }
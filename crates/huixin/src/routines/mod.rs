@@ -1,11 +1,67 @@
+use std::{
+    sync::{Arc, mpsc},
+    thread,
+};
+
+use crate::model::state::CentralState;
+
+pub mod beeper;
 pub mod guardian;
 pub mod instructor;
 pub mod metronome;
+pub mod remote_control;
 pub mod sheet_reader;
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum RoutineId {
+    Beeper,
     Instructor,
     Metronome,
+    RemoteControl,
     SheetReader,
 }
+
+/// Spawns the playback routines, along with the guardian watching over them.
+pub fn launch(state: Arc<CentralState>) {
+    let (msg_tx, msg_rx) = mpsc::channel();
+    let (beep_tx, beep_rx) = mpsc::channel();
+    state.loopback_sink_mut().connect_beeper(beep_tx);
+    let routines = vec![
+        (
+            RoutineId::Metronome,
+            thread::spawn({
+                let state = state.clone();
+                move || metronome::main(state)
+            }),
+        ),
+        (
+            RoutineId::SheetReader,
+            thread::spawn({
+                let state = state.clone();
+                move || sheet_reader::main(state, msg_tx)
+            }),
+        ),
+        (
+            RoutineId::Instructor,
+            thread::spawn({
+                let state = state.clone();
+                move || instructor::main(state, msg_rx)
+            }),
+        ),
+        (
+            RoutineId::RemoteControl,
+            thread::spawn({
+                let state = state.clone();
+                move || remote_control::main(state)
+            }),
+        ),
+        (
+            RoutineId::Beeper,
+            thread::spawn({
+                let state = state.clone();
+                move || beeper::main(state, beep_rx)
+            }),
+        ),
+    ];
+    thread::spawn(move || guardian::main(state, routines));
+}
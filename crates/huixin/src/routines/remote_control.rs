@@ -0,0 +1,324 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, mpsc},
+    thread,
+    time::Duration,
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use ws::{Message, WebSocket};
+
+use crate::model::state::CentralState;
+
+pub const DEFAULT_REMOTE_CONTROL_ADDR: &str = "127.0.0.1:3100";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// LYN: Remote Control Main Routine
+
+/// Serves transport control over WebSocket while enabled.
+///
+/// Clients send JSON commands (`{"cmd": "play"}`, `{"cmd": "seek", "tick": 0}`, ...) and are
+/// pushed the transport state whenever it changes, as well as in reply to every command.
+pub fn main(state: Arc<CentralState>) -> ! {
+    info!("Remote-control started");
+
+    let mut server: Option<Server> = None;
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        let config = state.remote_config();
+        let wanted_addr = config.enabled.then_some(config.addr);
+        if server.as_ref().map(|server| &server.addr) != wanted_addr.as_ref() {
+            server = None;
+            let Some(addr) = wanted_addr else {
+                state.remote_set_status(RemoteStatus::Disabled);
+                continue;
+            };
+            match Server::bind(addr.clone()) {
+                Ok(bound) => {
+                    info!("Remote control listening on {addr}");
+                    server = Some(bound);
+                }
+                Err(e) => {
+                    warn!("Failed to listen for remote control on {addr}: {e}");
+                    state.remote_set_status(RemoteStatus::Failed(e.to_string()));
+                    // retry once the config changes
+                    server = Some(Server::failed(addr));
+                    continue;
+                }
+            }
+        }
+
+        if let Some(server) = server.as_mut()
+            && server.listener.is_some()
+        {
+            server.serve(&state);
+            state.remote_set_status(RemoteStatus::Listening {
+                clients: server.clients.len(),
+            });
+        }
+    }
+}
+
+// LYN: Remote Control Config
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteControlConfig {
+    pub enabled: bool,
+    pub addr: String,
+}
+
+impl Default for RemoteControlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            addr: DEFAULT_REMOTE_CONTROL_ADDR.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RemoteStatus {
+    #[default]
+    Disabled,
+    Listening {
+        clients: usize,
+    },
+    Failed(String),
+}
+
+// LYN: Remote Control Protocol
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum RemoteCommand {
+    Play,
+    Pause,
+    Toggle,
+    Stop,
+    Seek {
+        tick: u64,
+    },
+    /// Overrides the tempo of the sheet and its tempo map, `null` goes back to them.
+    Bpm {
+        bpm: Option<f64>,
+    },
+    State,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum RemoteEvent {
+    Transport(TransportState),
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct TransportState {
+    playing: bool,
+    tick: u64,
+    tick_limit: u64,
+    ticks_per_beat: u64,
+    /// BPM the metronome runs at, with tempo changes on the timeline applied.
+    bpm: f64,
+}
+
+impl TransportState {
+    fn capture(state: &CentralState) -> Self {
+        Self {
+            playing: state.metro_playing(),
+            tick: state.metro_tick(),
+            tick_limit: state.metro_tick_limit(),
+            ticks_per_beat: state.sheet_ticks_per_beat(),
+            bpm: state.metro_bpm(),
+        }
+    }
+
+    /// Whether clients should hear about the change, position updates are only pushed once
+    /// per beat during playback.
+    fn differs_notably(&self, other: &Self) -> bool {
+        let beat = |transport: &Self| transport.tick / transport.ticks_per_beat.max(1);
+        self.playing != other.playing
+            || self.tick_limit != other.tick_limit
+            || self.ticks_per_beat != other.ticks_per_beat
+            || self.bpm != other.bpm
+            || if self.playing {
+                beat(self) != beat(other) || self.tick < other.tick
+            } else {
+                self.tick != other.tick
+            }
+    }
+}
+
+fn apply_command(state: &CentralState, command: RemoteCommand) -> Result<(), String> {
+    match command {
+        RemoteCommand::Play => state.metro_toggle_playing(Some(true)),
+        RemoteCommand::Pause => state.metro_toggle_playing(Some(false)),
+        RemoteCommand::Toggle => state.metro_toggle_playing(None),
+        RemoteCommand::Stop => state.metro_make_stop(),
+        RemoteCommand::Seek { tick } => {
+            let limit = state.metro_tick_limit();
+            if tick > limit {
+                return Err(format!(
+                    "tick {tick} is beyond the end of the sheet ({limit})"
+                ));
+            }
+            state.metro_seek(tick);
+        }
+        RemoteCommand::Bpm { bpm } => {
+            if let Some(bpm) = bpm
+                && !(1. ..=640.).contains(&bpm)
+            {
+                return Err(format!("bpm {bpm} is out of range (1 to 640)"));
+            }
+            state.metro_override_bpm(bpm);
+        }
+        RemoteCommand::State => {}
+    }
+    Ok(())
+}
+
+// LYN: Server
+
+/// Outcome of a handshake with the given peer.
+type Handshake = (SocketAddr, Result<WebSocket<TcpStream>, String>);
+
+#[derive(Debug)]
+struct Server {
+    addr: String,
+    /// `None` if binding failed.
+    listener: Option<TcpListener>,
+    clients: Vec<WebSocket<TcpStream>>,
+    /// Handshakes run on their own threads, so a slow peer does not hold up the others.
+    handshakes: (mpsc::Sender<Handshake>, mpsc::Receiver<Handshake>),
+    last_pushed: Option<TransportState>,
+}
+
+impl Server {
+    fn bind(addr: String) -> io::Result<Self> {
+        let listener = TcpListener::bind(&addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            addr,
+            listener: Some(listener),
+            clients: Vec::new(),
+            handshakes: mpsc::channel(),
+            last_pushed: None,
+        })
+    }
+    fn failed(addr: String) -> Self {
+        Self {
+            addr,
+            listener: None,
+            clients: Vec::new(),
+            handshakes: mpsc::channel(),
+            last_pushed: None,
+        }
+    }
+
+    fn serve(&mut self, state: &CentralState) {
+        self.accept_clients(state);
+
+        self.clients.retain_mut(|client| {
+            loop {
+                match client.read() {
+                    Ok(Message::Text(text)) => {
+                        let event = match json::from_str::<RemoteCommand>(&text) {
+                            Ok(command) => match apply_command(state, command) {
+                                Ok(()) => RemoteEvent::Transport(TransportState::capture(state)),
+                                Err(message) => RemoteEvent::Error { message },
+                            },
+                            Err(e) => RemoteEvent::Error {
+                                message: format!("invalid command: {e}"),
+                            },
+                        };
+                        if !send_event(client, &event) {
+                            return false;
+                        }
+                    }
+                    Ok(Message::Close(_)) => return false,
+                    Ok(_) => {}
+                    Err(ws::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                        return match client.flush() {
+                            Ok(()) => true,
+                            result => is_would_block(result),
+                        };
+                    }
+                    Err(_) => return false,
+                }
+            }
+        });
+
+        let transport = TransportState::capture(state);
+        if self
+            .last_pushed
+            .is_none_or(|last| transport.differs_notably(&last))
+        {
+            let event = RemoteEvent::Transport(transport);
+            self.clients.retain_mut(|client| send_event(client, &event));
+            self.last_pushed = Some(transport);
+        }
+    }
+
+    fn accept_clients(&mut self, state: &CentralState) {
+        let Some(listener) = self.listener.as_ref() else {
+            return;
+        };
+        while let Ok((stream, peer)) = listener.accept() {
+            let handshake_tx = self.handshakes.0.clone();
+            thread::spawn(move || {
+                // the server may be gone by now, the connection is then simply dropped
+                let _ = handshake_tx.send((peer, handshake(stream)));
+            });
+        }
+        while let Ok((peer, result)) = self.handshakes.1.try_recv() {
+            match result {
+                Ok(mut client) => {
+                    info!("Remote control client {peer} connected");
+                    let event = RemoteEvent::Transport(TransportState::capture(state));
+                    if send_event(&mut client, &event) {
+                        self.clients.push(client);
+                    }
+                }
+                Err(e) => warn!("Remote control handshake with {peer} failed: {e}"),
+            }
+        }
+    }
+}
+
+// LYN: Helpers
+
+/// Handshakes while blocking (on a thread of its own), the connection is then polled without
+/// blocking.
+fn handshake(stream: TcpStream) -> Result<WebSocket<TcpStream>, String> {
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let client = ws::accept(stream).map_err(|e| e.to_string())?;
+    client
+        .get_ref()
+        .set_nonblocking(true)
+        .map_err(|e| e.to_string())?;
+    Ok(client)
+}
+
+/// Returns `false` once the client is gone.
+fn send_event(client: &mut WebSocket<TcpStream>, event: &RemoteEvent) -> bool {
+    let Ok(text) = json::to_string(event) else {
+        return true;
+    };
+    match client.send(Message::text(text)) {
+        Ok(()) => true,
+        result => is_would_block(result),
+    }
+}
+
+#[inline]
+fn is_would_block(result: Result<(), ws::Error>) -> bool {
+    matches!(result, Err(ws::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock)
+}
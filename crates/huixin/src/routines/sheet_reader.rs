@@ -1,52 +1,187 @@
 use std::{
     sync::{Arc, mpsc},
-    thread,
-    time::Duration,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
-use log::info;
+use log::{info, warn};
 
 use crate::{
     app::PlayerContext,
     model::{
-        comm::SheetMessage, pattern::SheetPatternTrait, state::CentralState, track::SheetTrackTrait,
+        comm::{SheetBatch, SheetMessage},
+        pattern::SheetPatternTrait,
+        state::CentralState,
+        track::SheetTrackTrait,
     },
-    routines::RoutineId,
+    routines::{RoutineId, metronome::MetroEvent},
 };
 
-const REQUEST_TICK_POLL_INTERVAL: Duration = Duration::from_millis(50);
-
 // LYN: Sheet Reader Main Routine
 
-pub fn main(state: Arc<CentralState>, msg_tx: mpsc::Sender<SheetMessage>) -> ! {
+pub fn main(state: Arc<CentralState>, msg_tx: mpsc::Sender<SheetBatch>) -> ! {
     info!("Sheet-reader started");
 
+    let ticks = state.metro_subscribe(RoutineId::SheetReader);
+    let mut reader = ReadAhead::default();
     loop {
-        let Some(tick) = state.metro_request_tick(RoutineId::SheetReader) else {
-            thread::sleep(REQUEST_TICK_POLL_INTERVAL);
-            continue;
-        };
-
-        match state.player_context() {
-            PlayerContext::Sheet => {
-                for track in state.sheet_tracks_iter() {
-                    for msg in track.read().msg_at(tick, state.clone()) {
-                        msg_tx
-                            .send(msg)
-                            .expect("Instruction messaging channel unexpectedly closed");
-                    }
+        match ticks
+            .recv()
+            .expect("Metronome unexpectedly stopped ticking")
+        {
+            MetroEvent::Tick { tick, at } => {
+                // reaching a tick later than the next one is due means running behind
+                let lag = at.elapsed();
+                if lag > state.metro_tick_interval() {
+                    warn!("Sheet-reader fell {lag:?} behind tick {tick}");
+                    state.metro_report_lag(RoutineId::SheetReader);
                 }
+                reader.advance(&state, &msg_tx, tick, at);
             }
-            PlayerContext::Pattern => {
-                let Some(pat) = state.selected_pattern() else {
-                    continue;
-                };
-                for msg in pat.read().msg_at(tick) {
-                    msg_tx
-                        .send(msg)
-                        .expect("Instruction messaging channel unexpectedly closed");
-                }
+            MetroEvent::Stopped => reader.stop(&state, &msg_tx),
+        }
+    }
+}
+
+// LYN: Read Ahead
+
+/// Reads the sheet up to the lookahead ahead of the metronome.
+///
+/// Messages read ahead are stamped with the time their tick is due, so receivers can schedule
+/// them precisely. Without lookahead, messages are sent unstamped as their tick is reached.
+#[derive(Debug, Default)]
+struct ReadAhead {
+    /// The last tick the metronome reached, and when it was due.
+    played: Option<(u64, Instant)>,
+    /// The last tick read, notes still sounding past it are released once playback stops,
+    /// wraps around or jumps elsewhere.
+    read: Option<u64>,
+    /// Ticks `read` is ahead of `played`.
+    ahead: i64,
+}
+
+impl ReadAhead {
+    fn advance(
+        &mut self,
+        state: &Arc<CentralState>,
+        msg_tx: &mpsc::Sender<SheetBatch>,
+        tick: u64,
+        at: Instant,
+    ) {
+        let limit = state.metro_tick_limit();
+        match self.played {
+            Some((played, _)) if tick == next_tick(played, limit) => self.ahead -= 1,
+            Some(_) => self.stop(state, msg_tx),
+            None => self.ahead = -1,
+        }
+        self.played = Some((tick, at));
+
+        let lookahead = state.comm_lookahead();
+        let interval = state.metro_tick_interval();
+        let max_ahead = (lookahead.as_secs_f64() / interval.as_secs_f64()) as i64;
+        while self.ahead < max_ahead {
+            let next = match self.read {
+                Some(read) => next_tick(read, limit),
+                None => tick,
+            };
+            let wraps = self.read.is_some_and(|read| next <= read);
+            if wraps && !state.metro_looping() {
+                break;
             }
-        };
+
+            self.ahead += 1;
+            let timestamp = (!lookahead.is_zero())
+                .then(|| to_timestamp(at + interval.mul_f64(self.ahead as f64)));
+            // notes released by wrapping around go out along with the first tick
+            let mut msgs = match self.read {
+                Some(read) if wraps => release_tick(state, read + 1),
+                _ => Vec::new(),
+            };
+            msgs.extend(read_tick(state, next));
+            send_batches(msg_tx, next, msgs, timestamp);
+            self.read = Some(next);
+        }
+    }
+
+    /// Releases whatever is still sounding once the last tick read is over.
+    ///
+    /// Messages already sent ahead can't be taken back, so the release is stamped with the time
+    /// the tick after them is due, keeping it after every note it ends.
+    fn stop(&mut self, state: &Arc<CentralState>, msg_tx: &mpsc::Sender<SheetBatch>) {
+        if let Some(read) = self.read.take() {
+            let timestamp = self
+                .played
+                .filter(|_| !state.comm_lookahead().is_zero())
+                .map(|(_, at)| {
+                    let interval = state.metro_tick_interval();
+                    to_timestamp(at + interval.mul_f64((self.ahead + 1).max(0) as f64))
+                });
+            send_batches(msg_tx, read + 1, release_tick(state, read + 1), timestamp);
+        }
+        self.played = None;
+        self.ahead = -1;
+    }
+}
+
+// LYN: Helpers
+
+#[inline]
+fn next_tick(tick: u64, limit: u64) -> u64 {
+    if tick >= limit { 0 } else { tick + 1 }
+}
+
+/// Converts an instant into seconds since the UNIX epoch.
+fn to_timestamp(instant: Instant) -> f64 {
+    let (now, wall) = (Instant::now(), SystemTime::now());
+    let wall = if instant >= now {
+        wall + (instant - now)
+    } else {
+        wall - (now - instant)
+    };
+    wall.duration_since(UNIX_EPOCH)
+        .map_or(0., |since| since.as_secs_f64())
+}
+
+/// Sends the messages due at the same time, a single batch to each target.
+fn send_batches(
+    msg_tx: &mpsc::Sender<SheetBatch>,
+    tick: u64,
+    msgs: Vec<SheetMessage>,
+    timestamp: Option<f64>,
+) {
+    for mut batch in SheetBatch::group(tick, msgs) {
+        for payload in batch.payloads.iter_mut() {
+            payload.timestamp = timestamp;
+        }
+        msg_tx
+            .send(batch)
+            .expect("Instruction messaging channel unexpectedly closed");
+    }
+}
+
+fn read_tick(state: &Arc<CentralState>, tick: u64) -> Vec<SheetMessage> {
+    match state.player_context() {
+        PlayerContext::Sheet => state
+            .sheet_tracks_ordered()
+            .into_iter()
+            .flat_map(|track| track.read().msg_at(tick, state.clone()))
+            .collect(),
+        PlayerContext::Pattern => state
+            .selected_pattern()
+            .map(|pat| pat.read().msg_at(tick))
+            .unwrap_or_default(),
+    }
+}
+
+fn release_tick(state: &Arc<CentralState>, tick: u64) -> Vec<SheetMessage> {
+    match state.player_context() {
+        PlayerContext::Sheet => state
+            .sheet_tracks_ordered()
+            .into_iter()
+            .flat_map(|track| track.read().release_at(tick, state.clone()))
+            .collect(),
+        PlayerContext::Pattern => state
+            .selected_pattern()
+            .map(|pat| pat.read().release_at(tick))
+            .unwrap_or_default(),
     }
 }
@@ -5,11 +5,21 @@ use serde::{Deserialize, Serialize};
 
 pub type DataMap = json::Map<String, json::Value>;
 
+/// Seconds between the NTP epoch (1900) and the UNIX epoch (1970).
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Format {
     #[default]
     WsBasedJson,
     TcpBasedOsc,
+    /// Binary OSC datagrams, as most OSC software expects them.
+    UdpOsc,
+    /// Plain Pd messages (`tag key value ...;`), ready for `[netreceive]` → `[route]`.
+    TcpBasedFudi,
+    UdpFudi,
+    /// Received and shown inside the app, sent as `WsBasedJson` would be.
+    Loopback,
 }
 
 impl Display for Format {
@@ -17,13 +27,28 @@ impl Display for Format {
         match self {
             Format::WsBasedJson => write!(f, "绘心系列协议"),
             Format::TcpBasedOsc => write!(f, "PureData OSC"),
+            Format::UdpOsc => write!(f, "OSC (UDP)"),
+            Format::TcpBasedFudi => write!(f, "PureData FUDI (TCP)"),
+            Format::UdpFudi => write!(f, "PureData FUDI (UDP)"),
+            Format::Loopback => write!(f, "内置回环"),
         }
     }
 }
 
 impl Format {
     pub fn variants() -> &'static [Format] {
-        &[Format::WsBasedJson, Format::TcpBasedOsc]
+        &[
+            Format::WsBasedJson,
+            Format::TcpBasedOsc,
+            Format::UdpOsc,
+            Format::TcpBasedFudi,
+            Format::UdpFudi,
+            Format::Loopback,
+        ]
+    }
+    /// Whether the format is sent as raw bytes rather than text.
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Format::UdpOsc)
     }
 }
 
@@ -31,6 +56,10 @@ impl Format {
 pub struct Instruction {
     pub tag: String,
     pub data: DataMap,
+    /// When the receiver should act on the instruction, in seconds since the UNIX epoch,
+    /// `None` for immediately.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f64>,
     #[serde(skip)]
     pub format: Option<Format>,
 }
@@ -49,7 +78,27 @@ impl ops::DerefMut for Instruction {
 }
 
 impl Instruction {
+    /// Forms a single FUDI message, `tag key value key value ...;`.
+    ///
+    /// Like OSC, values that are arrays or objects are left out, and so are nulls which FUDI
+    /// has no atom for.
+    fn into_fudi_message(self) -> String {
+        let mut atoms = vec![escape_fudi_atom(self.tag.trim_matches('/'))];
+        for (key, value) in self.data {
+            let value = match value {
+                json::Value::Bool(b) => String::from(if b { "1" } else { "0" }),
+                json::Value::Number(n) => n.to_string(),
+                json::Value::String(s) if !s.is_empty() => escape_fudi_atom(&s),
+                _ => continue,
+            };
+            atoms.push(escape_fudi_atom(&key));
+            atoms.push(value);
+        }
+        atoms.join(" ") + ";\n"
+    }
+
     fn into_osc_packet(self) -> OscPacket {
+        let timetag = osc_timetag(self.timestamp);
         let addr_prefix = if self.tag.starts_with('/') {
             self.tag
         } else {
@@ -84,29 +133,111 @@ impl Instruction {
             .collect();
 
         OscPacket::Bundle(osc::OscBundle {
-            timetag: osc::OscTime {
-                seconds: 0,
-                fractional: 1,
-            },
+            timetag,
             content: messages,
         })
     }
+
+    /// Nests the bundle of every instruction in a single bundle.
+    fn into_osc_batch(batch: Vec<Instruction>) -> OscPacket {
+        // instructions batched together are due at the same time
+        let timetag = osc_timetag(batch.first().and_then(|instruction| instruction.timestamp));
+        OscPacket::Bundle(osc::OscBundle {
+            timetag,
+            content: batch.into_iter().map(Self::into_osc_packet).collect(),
+        })
+    }
 }
 
 impl Instruction {
+    /// Encodes the instruction as it is sent over the wire.
+    #[inline]
+    pub fn form_bytes(self, format: Format) -> Option<Vec<u8>> {
+        match format {
+            Format::UdpOsc => osc::encoder::encode(&self.into_osc_packet()).ok(),
+            _ => self.form_string(format).map(String::into_bytes),
+        }
+    }
+    /// Encodes the instruction for text based formats, `None` for binary ones.
     #[inline]
     pub fn form_string(self, format: Format) -> Option<String> {
         match format {
-            Format::WsBasedJson => json::to_string(&self).ok(),
+            Format::WsBasedJson | Format::Loopback => json::to_string(&self).ok(),
             Format::TcpBasedOsc => osc::encoder::encode(&self.into_osc_packet())
                 .ok()
-                .map(|pak| {
-                    pak.iter()
-                        .map(|b| b.to_string())
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                        + ";"
-                }),
+                .map(osc_bytes_to_string),
+            Format::UdpOsc => None,
+            Format::TcpBasedFudi | Format::UdpFudi => Some(self.into_fudi_message()),
+        }
+    }
+
+    /// Encodes instructions due together as one payload, so they arrive at once: a JSON array,
+    /// a single OSC bundle, or consecutive FUDI messages. A lone instruction is encoded as is.
+    pub fn form_batch_bytes(mut batch: Vec<Instruction>, format: Format) -> Option<Vec<u8>> {
+        if batch.len() == 1 {
+            return batch.pop()?.form_bytes(format);
+        }
+        match format {
+            Format::UdpOsc => osc::encoder::encode(&Self::into_osc_batch(batch)).ok(),
+            _ => Self::form_batch_string(batch, format).map(String::into_bytes),
+        }
+    }
+    /// Like [`Instruction::form_batch_bytes`], `None` for binary formats.
+    pub fn form_batch_string(mut batch: Vec<Instruction>, format: Format) -> Option<String> {
+        if batch.len() == 1 {
+            return batch.pop()?.form_string(format);
+        }
+        match format {
+            Format::WsBasedJson | Format::Loopback => json::to_string(&batch).ok(),
+            Format::TcpBasedOsc => osc::encoder::encode(&Self::into_osc_batch(batch))
+                .ok()
+                .map(osc_bytes_to_string),
+            Format::UdpOsc => None,
+            Format::TcpBasedFudi | Format::UdpFudi => {
+                Some(batch.into_iter().map(Self::into_fudi_message).collect())
+            }
         }
     }
 }
+
+/// Writes the bytes of an OSC packet as a Pd list, e.g. for `[oscparse]`.
+fn osc_bytes_to_string(packet: Vec<u8>) -> String {
+    packet
+        .iter()
+        .map(|b| b.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+        + ";"
+}
+
+/// Returns the time tag for the timestamp, `None` being the special time tag for immediately.
+fn osc_timetag(timestamp: Option<f64>) -> osc::OscTime {
+    timestamp.map_or(
+        osc::OscTime {
+            seconds: 0,
+            fractional: 1,
+        },
+        timestamp_to_osc_time,
+    )
+}
+
+/// Escapes whatever Pd would otherwise treat as a separator or dollar argument.
+fn escape_fudi_atom(atom: &str) -> String {
+    let mut escaped = String::with_capacity(atom.len());
+    for c in atom.chars() {
+        if matches!(c, ' ' | '\t' | '\n' | ';' | ',' | '\\' | '$') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Converts seconds since the UNIX epoch into an NTP time tag.
+fn timestamp_to_osc_time(timestamp: f64) -> osc::OscTime {
+    let timestamp = timestamp.max(0.);
+    osc::OscTime {
+        seconds: (timestamp.trunc() as u64 + NTP_UNIX_OFFSET_SECS) as u32,
+        fractional: (timestamp.fract() * (1u64 << 32) as f64) as u32,
+    }
+}